@@ -1,5 +1,8 @@
 use hkdf::Hkdf;
 
+/// Default PBKDF2 iteration count used when stretching passphrases.
+pub const DEFAULT_PASSPHRASE_ITERATIONS: u32 = 210_000;
+
 pub struct Deriver {
     salt: Option<Vec<u8>>,
     ikm: Vec<u8>,
@@ -16,4 +19,17 @@ impl Deriver {
         hk.expand(info, &mut okm).expect("HKDF expansion failed");
         okm
     }
+
+    /// Stretches a low-entropy secret (e.g. a user passphrase) into a 32 byte key
+    /// using PBKDF2-HMAC-SHA3-256. The input keying material is used as the password.
+    pub fn derive_passphrase_key(&self, iterations: u32) -> zeroize::Zeroizing<Vec<u8>> {
+        let mut okm = zeroize::Zeroizing::new(vec![0u8; 32]);
+        pbkdf2::pbkdf2_hmac::<sha3::Sha3_256>(
+            &self.ikm,
+            self.salt.as_deref().unwrap_or_default(),
+            iterations,
+            &mut okm,
+        );
+        okm
+    }
 }
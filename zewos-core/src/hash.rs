@@ -1,4 +1,5 @@
 pub use hex::{FromHex, ToHex};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
@@ -13,6 +14,14 @@ impl Sha256 {
         Self(result.into())
     }
 
+    /// HMAC-SHA3-256 of `data` under `key`.
+    pub fn keyed(key: &[u8], data: &[u8]) -> Self {
+        let mut mac =
+            Hmac::<Sha3_256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        Self(mac.finalize().into_bytes().into())
+    }
+
     pub fn as_bytes(&self) -> &[u8; 32] {
         &self.0
     }
@@ -78,6 +87,14 @@ mod tests {
         assert_eq!(sha256.0.len(), 32);
     }
 
+    #[test]
+    fn test_sha256_keyed() {
+        let a = Sha256::keyed(b"key", b"data");
+        assert_eq!(a, Sha256::keyed(b"key", b"data"));
+        assert_ne!(a, Sha256::keyed(b"other", b"data"));
+        assert_ne!(a, Sha256::new(b"data"));
+    }
+
     #[test]
     fn test_sha256_to_hex() {
         let data = b"test data";
@@ -5,3 +5,4 @@ pub mod hash;
 pub mod logging;
 pub mod metadata;
 pub mod permissions;
+pub mod signing;
@@ -1,80 +1,454 @@
+use super::hash::{FromHex, Sha256, ToHex};
 use anyhow::Result;
-use chrono::{Local, NaiveTime};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success => write!(f, "success"),
+            Outcome::Failure => write!(f, "failure"),
+        }
+    }
+}
+
+/// Category of the error behind a failed operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    KeyNotFound,
+    Decrypt,
+    Io,
+    Permission,
+    Corruption,
+    Rollback,
+    Conflict,
+    Busy,
+    Quota,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::KeyNotFound => "key_not_found",
+            ErrorKind::Decrypt => "decrypt",
+            ErrorKind::Io => "io",
+            ErrorKind::Permission => "permission",
+            ErrorKind::Corruption => "corruption",
+            ErrorKind::Rollback => "rollback",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::Busy => "busy",
+            ErrorKind::Quota => "quota",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single audit record, stored as one JSON line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Log {
-    details: String,
-    action: String,
-    additional_info: String,
-    timestamp: NaiveTime,
+    timestamp: DateTime<Utc>,
+    operation: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error_kind: Option<ErrorKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    details: Option<String>,
+    session_id: String,
+    pid: u32,
+    os_user: String,
 }
 
 impl Log {
-    pub fn new(details: String, action: String, additional_info: String) -> Log {
+    pub fn new(operation: impl Into<String>, outcome: Outcome) -> Log {
         Log {
-            details,
-            action,
-            additional_info,
-            timestamp: Local::now().time(),
+            timestamp: Utc::now(),
+            operation: operation.into(),
+            key_id: None,
+            outcome,
+            error_kind: None,
+            error_id: None,
+            details: None,
+            session_id: String::new(),
+            pid: std::process::id(),
+            os_user: whoami::username(),
         }
     }
 
-    pub fn details(&self) -> &str {
-        &self.details
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
+    }
+
+    pub fn with_error_kind(mut self, error_kind: ErrorKind) -> Self {
+        self.error_kind = Some(error_kind);
+        self
     }
 
-    pub fn action(&self) -> &str {
-        &self.action
+    /// Marks the record as a failure of `error_kind` with a fresh id, so the record can be
+    /// found again from an error report.
+    pub fn with_error(self, error_kind: ErrorKind) -> Self {
+        self.with_error_kind(error_kind)
+            .with_error_id(uuid::Uuid::new_v4().to_string())
     }
 
-    pub fn additional_info(&self) -> &str {
-        &self.additional_info
+    pub fn with_error_id(mut self, error_id: impl Into<String>) -> Self {
+        self.error_id = Some(error_id.into());
+        self
     }
 
-    pub fn timestamp(&self) -> &NaiveTime {
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    pub fn with_session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = session_id.into();
+        self
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
         &self.timestamp
     }
+
+    pub fn operation(&self) -> &str {
+        &self.operation
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    pub fn error_kind(&self) -> Option<ErrorKind> {
+        self.error_kind
+    }
+
+    pub fn error_id(&self) -> Option<&str> {
+        self.error_id.as_deref()
+    }
+
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn os_user(&self) -> &str {
+        &self.os_user
+    }
+
     pub fn serialize(&self) -> String {
-        format!(
-            "[{}] {}:{}:{}",
-            self.timestamp.format("%H:%M:%S"),
-            self.action,
-            self.details,
-            self.additional_info
-        )
+        serde_json::to_string(self).expect("log records always serialize")
+    }
+
+    pub fn parse(line: &str) -> Option<Log> {
+        serde_json::from_str(line).ok()
+    }
+
+    /// Human-readable single line rendering of the record.
+    pub fn render(&self) -> String {
+        let mut line = format!(
+            "{} {}",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.operation
+        );
+        if let Some(key_id) = &self.key_id {
+            line.push_str(&format!(" key={}", key_id));
+        }
+        line.push_str(&format!(" {}", self.outcome));
+        match (&self.error_kind, &self.error_id) {
+            (Some(kind), Some(id)) => line.push_str(&format!(" ({}, error {})", kind, id)),
+            (Some(kind), None) => line.push_str(&format!(" ({})", kind)),
+            (None, Some(id)) => line.push_str(&format!(" (error {})", id)),
+            (None, None) => {}
+        }
+        if let Some(details) = &self.details {
+            line.push_str(&format!(" {}", details));
+        }
+        line.push_str(&format!(
+            " [session={} pid={} user={}]",
+            self.session_id, self.pid, self.os_user
+        ));
+        line
+    }
+}
+
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// Position of the last record in the audit chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainHead {
+    /// Sequence number the next record will get.
+    pub seq: u64,
+    /// Hash of the last record, or all zeroes before the first one.
+    pub hash: Sha256,
+}
+
+impl ChainHead {
+    pub fn genesis() -> Self {
+        Self {
+            seq: 0,
+            hash: Sha256([0u8; 32]),
+        }
+    }
+
+    pub fn next(&self, content: &str) -> Self {
+        Self {
+            seq: self.seq + 1,
+            hash: chain_hash(&self.hash, self.seq, content),
+        }
+    }
+
+    /// Message covered by the periodic signature over the chain head.
+    pub fn signing_message(&self) -> Vec<u8> {
+        let mut message = b"zewos-log-head".to_vec();
+        message.extend_from_slice(&self.seq.to_be_bytes());
+        message.extend_from_slice(self.hash.as_bytes());
+        message
+    }
+}
+
+impl Default for ChainHead {
+    fn default() -> Self {
+        Self::genesis()
+    }
+}
+
+pub fn chain_hash(prev: &Sha256, seq: u64, content: &str) -> Sha256 {
+    let mut data = prev.as_bytes().to_vec();
+    data.extend_from_slice(&seq.to_be_bytes());
+    data.extend_from_slice(content.as_bytes());
+    Sha256::new(&data)
+}
+
+const SIGNATURE_PREFIX: &str = "#signature";
+
+/// A single line of a session file.
+///
+/// Records are written as `content|seq|prev_hash|hash`, signatures over the chain head
+/// as `#signature|seq|head_hash|signature`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogLine {
+    Record {
+        content: String,
+        seq: u64,
+        prev_hash: Sha256,
+        hash: Sha256,
+    },
+    Signature {
+        head: ChainHead,
+        signature: Vec<u8>,
+    },
+}
+
+impl LogLine {
+    pub fn serialize(&self) -> String {
+        match self {
+            LogLine::Record {
+                content,
+                seq,
+                prev_hash,
+                hash,
+            } => format!(
+                "{}|{}|{}|{}",
+                content,
+                seq,
+                prev_hash.encode_hex::<String>(),
+                hash.encode_hex::<String>()
+            ),
+            LogLine::Signature { head, signature } => format!(
+                "{}|{}|{}|{}",
+                SIGNATURE_PREFIX,
+                head.seq,
+                head.hash.encode_hex::<String>(),
+                hex::encode(signature)
+            ),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<LogLine> {
+        // The content may contain '|', so the fixed fields are split off from the right.
+        let mut fields = line.rsplitn(4, '|');
+        let last = fields.next()?;
+        let middle = fields.next()?;
+        let seq = fields.next()?.parse().ok()?;
+        let first = fields.next()?;
+        let hash = Sha256::from_hex(middle).ok()?;
+
+        if first == SIGNATURE_PREFIX {
+            return Some(LogLine::Signature {
+                head: ChainHead { seq, hash },
+                signature: hex::decode(last).ok()?,
+            });
+        }
+        Some(LogLine::Record {
+            content: first.to_string(),
+            seq,
+            prev_hash: hash,
+            hash: Sha256::from_hex(last).ok()?,
+        })
     }
 }
 
 #[derive(Clone)]
 pub struct LogFileStruct {
-    logs: Vec<Log>,
+    lines: Vec<LogLine>,
+    head: ChainHead,
 }
 
 impl LogFileStruct {
     pub fn new() -> Self {
-        Self { logs: vec![] }
-    }
-    pub fn insert(
-        &mut self,
-        details: String,
-        action: String,
-        additional_info: String,
-    ) -> Result<()> {
-        self.add(Log::new(details, action, additional_info))?;
-        Ok(())
+        Self::with_head(ChainHead::genesis())
+    }
+
+    pub fn with_head(head: ChainHead) -> Self {
+        Self {
+            lines: vec![],
+            head,
+        }
     }
 
     pub fn add(&mut self, log: Log) -> Result<()> {
-        self.logs.push(log);
+        let content = log.serialize();
+        let next = self.head.next(&content);
+        self.lines.push(LogLine::Record {
+            content,
+            seq: self.head.seq,
+            prev_hash: self.head.hash,
+            hash: next.hash,
+        });
+        self.head = next;
         Ok(())
     }
 
+    pub fn add_signature(&mut self, signature: Vec<u8>) {
+        self.lines.push(LogLine::Signature {
+            head: self.head,
+            signature,
+        });
+    }
+
+    pub fn head(&self) -> ChainHead {
+        self.head
+    }
+
+    pub fn lines(&self) -> &[LogLine] {
+        &self.lines
+    }
+
+    /// Removes and returns the lines added so far, keeping the chain head.
+    pub fn take_lines(&mut self) -> Vec<LogLine> {
+        std::mem::take(&mut self.lines)
+    }
+
     pub fn serialize(&self) -> String {
         let mut result = String::new();
-        for log in &self.logs {
-            result.push_str(&log.serialize());
+        for line in &self.lines {
+            result.push_str(&line.serialize());
             result.push('\n');
         }
         result
     }
 }
+
+impl Default for LogFileStruct {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_links_records() {
+        let mut file = LogFileStruct::new();
+        file.add(Log::new("get", Outcome::Success).with_key_id("a|b"))
+            .unwrap();
+        file.add(Log::new("insert", Outcome::Failure).with_error_kind(ErrorKind::Io))
+            .unwrap();
+        file.add_signature(vec![1, 2, 3]);
+
+        let lines: Vec<_> = file.serialize().lines().map(LogLine::parse).collect();
+        let mut head = ChainHead::genesis();
+        for line in lines.iter().take(2) {
+            match line.as_ref().unwrap() {
+                LogLine::Record {
+                    content,
+                    seq,
+                    prev_hash,
+                    hash,
+                } => {
+                    assert_eq!(*seq, head.seq);
+                    assert_eq!(*prev_hash, head.hash);
+                    head = head.next(content);
+                    assert_eq!(*hash, head.hash);
+                }
+                LogLine::Signature { .. } => panic!("expected a record"),
+            }
+        }
+        assert_eq!(head, file.head());
+        assert_eq!(
+            lines[2],
+            Some(LogLine::Signature {
+                head,
+                signature: vec![1, 2, 3]
+            })
+        );
+    }
+
+    #[test]
+    fn test_log_json_roundtrip() {
+        let log = Log::new("get", Outcome::Failure)
+            .with_key_id("hmac-00")
+            .with_error_kind(ErrorKind::KeyNotFound)
+            .with_session_id("session");
+        let line = log.serialize();
+        assert!(!line.contains('\n'));
+        assert_eq!(Log::parse(&line), Some(log.clone()));
+
+        let rendered = log.render();
+        assert!(rendered.contains("get key=hmac-00 failure (key_not_found)"));
+        assert!(rendered.contains("session=session"));
+        assert!(Log::parse("[12:00:00] get:zewos_request:success").is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!(LogLine::parse("[12:00:00] get:zewos_request:success").is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use super::hash::Sha256;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +9,25 @@ pub struct BackupMetadata {
     pub total_size: usize,
     pub object_count: u64,
     pub compression_level: Option<usize>,
+    /// Root of the Merkle tree over the stored objects.
+    #[serde(default)]
+    pub merkle_root: Option<Sha256>,
+    /// Incremented on every mutation, used to detect rollbacks to older copies.
+    #[serde(default)]
+    pub generation: u64,
+    /// Binds `generation` to `merkle_root`.
+    #[serde(default)]
+    pub state_hash: Option<Sha256>,
+    /// Signature over `state_hash` by the store's key, as last saved. Cleared by any change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_signature: Option<Vec<u8>>,
+    /// Root of the Merkle tree over the retained previous values of each key, `None` while
+    /// there are none.
+    #[serde(default)]
+    pub history_hash: Option<Sha256>,
+    /// Encoding of the stored objects. Stores from before it was recorded are format 0.
+    #[serde(default)]
+    pub object_format: u32,
 }
 
 impl BackupMetadata {
@@ -18,9 +38,20 @@ impl BackupMetadata {
             compression_level,
             object_count: 0,
             last_modified: Utc::now(),
+            merkle_root: None,
+            generation: 0,
+            state_hash: None,
+            state_signature: None,
+            history_hash: None,
+            object_format: 0,
         }
     }
 
+    pub fn compute_state_hash(&self) -> Option<Sha256> {
+        self.merkle_root
+            .map(|root| state_hash(self.generation, &root, self.history_hash.as_ref()))
+    }
+
     pub fn update_compression_ratio(&mut self, original_size: u64) {
         if original_size > 0 {
             self.compression_level =
@@ -37,6 +68,23 @@ impl Default for BackupMetadata {
             compression_level: Some(3),
             object_count: 0,
             last_modified: Utc::now(),
+            merkle_root: None,
+            generation: 0,
+            state_hash: None,
+            state_signature: None,
+            history_hash: None,
+            object_format: 0,
         }
     }
 }
+
+/// Hash binding a store's `generation` to its Merkle `root` and retained history.
+pub fn state_hash(generation: u64, root: &Sha256, history_hash: Option<&Sha256>) -> Sha256 {
+    let mut data = b"zewos-state".to_vec();
+    data.extend_from_slice(&generation.to_be_bytes());
+    data.extend_from_slice(root.as_bytes());
+    if let Some(history) = history_hash {
+        data.extend_from_slice(history.as_bytes());
+    }
+    Sha256::new(&data)
+}
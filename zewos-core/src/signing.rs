@@ -0,0 +1,77 @@
+use super::derive::Deriver;
+use super::errors::{KeypairError, SignatureError};
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+
+#[derive(Clone)]
+pub struct Keypair {
+    signing_key: SigningKey,
+    verifying_key: VerifyingKey,
+}
+
+impl Keypair {
+    /// Deterministically derives a P-256 keypair from `seed`, so the same machine (or the
+    /// same secret) always yields the same key.
+    pub fn from_seed(seed: &[u8], info: &[u8]) -> Result<Self, KeypairError> {
+        let secret = zeroize::Zeroizing::new(Deriver::new(None, seed.to_vec()).derive_key(info));
+        let signing_key = SigningKey::from_slice(&secret)?;
+        let verifying_key = *signing_key.verifying_key();
+        Ok(Self {
+            signing_key,
+            verifying_key,
+        })
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        let signature: Signature = self.signing_key.sign(message);
+        signature.to_bytes().to_vec()
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), SignatureError> {
+        let signature = Signature::from_slice(signature)
+            .map_err(|e| SignatureError::InvalidKeyFormat(e.to_string()))?;
+        self.verifying_key
+            .verify(message, &signature)
+            .map_err(|_| SignatureError::InvalidSignature)
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        self.verifying_key.to_sec1_bytes().to_vec()
+    }
+}
+
+/// Verifies `signature` over `message` against `public_key`, as given by
+/// [`Keypair::public_key`].
+pub fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), SignatureError> {
+    let verifying_key = VerifyingKey::from_sec1_bytes(public_key)
+        .map_err(|e| SignatureError::InvalidKeyFormat(e.to_string()))?;
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| SignatureError::InvalidKeyFormat(e.to_string()))?;
+    verifying_key
+        .verify(message, &signature)
+        .map_err(|_| SignatureError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let a = Keypair::from_seed(b"seed", b"info").unwrap();
+        let b = Keypair::from_seed(b"seed", b"info").unwrap();
+        let c = Keypair::from_seed(b"seed", b"other").unwrap();
+        assert_eq!(a.public_key(), b.public_key());
+        assert_ne!(a.public_key(), c.public_key());
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keypair = Keypair::from_seed(b"seed", b"info").unwrap();
+        let signature = keypair.sign(b"message");
+        assert!(keypair.verify(b"message", &signature).is_ok());
+        assert!(keypair.verify(b"tampered", &signature).is_err());
+        assert!(verify(&keypair.public_key(), b"message", &signature).is_ok());
+        assert!(verify(&keypair.public_key(), b"tampered", &signature).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use super::encrypt::{Aes256Gcm, AES};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use zewos_core::derive::{Deriver, DEFAULT_PASSPHRASE_ITERATIONS};
+
+const MAGIC: &[u8; 8] = b"ZEWOSARC";
+const VERSION: u8 = 1;
+const KDF_PBKDF2_SHA3_256: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 1 + 4 + SALT_LEN;
+
+/// Parameters used to turn the archive passphrase into an encryption key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub iterations: u32,
+    pub salt: [u8; SALT_LEN],
+}
+
+impl KdfParams {
+    pub fn new(iterations: u32) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self { iterations, salt }
+    }
+
+    fn cipher(&self, passphrase: &str) -> AES<Aes256Gcm> {
+        let deriver = Deriver::new(Some(self.salt.to_vec()), passphrase.as_bytes().to_vec());
+        AES::new(deriver.derive_passphrase_key(self.iterations))
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::new(DEFAULT_PASSPHRASE_ITERATIONS)
+    }
+}
+
+/// Self-contained, passphrase protected container used to move a store between machines.
+///
+/// Layout: `MAGIC | version | kdf id | iterations (LE u32) | salt | nonce | ciphertext`.
+/// Unlike the files inside `Directory`, an archive is not bound to the machine fingerprint.
+pub struct Archive {
+    kdf: KdfParams,
+    ciphertext: Vec<u8>,
+}
+
+impl Archive {
+    pub fn seal(payload: &[u8], passphrase: &str) -> io::Result<Self> {
+        Self::seal_with_params(payload, passphrase, KdfParams::default())
+    }
+
+    pub fn seal_with_params(payload: &[u8], passphrase: &str, kdf: KdfParams) -> io::Result<Self> {
+        let ciphertext = kdf
+            .cipher(passphrase)
+            .encrypt(payload, None)
+            .map_err(|_| io::Error::other("Failed to encrypt archive"))?;
+        Ok(Self { kdf, ciphertext })
+    }
+
+    pub fn open(&self, passphrase: &str) -> io::Result<Vec<u8>> {
+        self.kdf
+            .cipher(passphrase)
+            .decrypt(&self.ciphertext)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Failed to decrypt archive: wrong passphrase or corrupted file",
+                )
+            })
+    }
+
+    pub fn kdf_params(&self) -> &KdfParams {
+        &self.kdf
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.ciphertext.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(KDF_PBKDF2_SHA3_256);
+        bytes.extend_from_slice(&self.kdf.iterations.to_le_bytes());
+        bytes.extend_from_slice(&self.kdf.salt);
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_archive("Not a Zewos archive"));
+        }
+        let mut offset = MAGIC.len();
+        if bytes[offset] != VERSION {
+            return Err(invalid_archive("Unsupported archive version"));
+        }
+        offset += 1;
+        if bytes[offset] != KDF_PBKDF2_SHA3_256 {
+            return Err(invalid_archive("Unsupported key derivation function"));
+        }
+        offset += 1;
+        let iterations = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        offset += 4;
+        let salt: [u8; SALT_LEN] = bytes[offset..offset + SALT_LEN].try_into().unwrap();
+        offset += SALT_LEN;
+
+        Ok(Self {
+            kdf: KdfParams { iterations, salt },
+            ciphertext: bytes[offset..].to_vec(),
+        })
+    }
+
+    /// Writes the archive readable by the owner only. The bytes go to a temporary file next
+    /// to `path` that is renamed into place, so an interrupted write never leaves a partial
+    /// archive behind.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        let tmp = path.with_file_name(name);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let result = options.open(&tmp).and_then(|mut file| {
+            file.write_all(&self.to_bytes())?;
+            file.sync_all()
+        });
+        if let Err(e) = result.and_then(|_| fs::rename(&tmp, path)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn invalid_archive(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn fast_params() -> KdfParams {
+        KdfParams::new(1_000)
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let archive = Archive::seal_with_params(b"payload", "secret", fast_params()).unwrap();
+        assert_eq!(archive.open("secret").unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let archive = Archive::seal_with_params(b"payload", "secret", fast_params()).unwrap();
+        let err = archive.open("not-the-secret").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_roundtrip_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("export.zewos");
+        let params = fast_params();
+        Archive::seal_with_params(b"payload", "secret", params)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+
+        let archive = Archive::read(&path).unwrap();
+        assert_eq!(archive.kdf_params(), &params);
+        assert_eq!(archive.open("secret").unwrap(), b"payload");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_rejects_garbage() {
+        assert!(Archive::from_bytes(b"definitely not an archive").is_err());
+    }
+}
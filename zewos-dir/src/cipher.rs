@@ -0,0 +1,53 @@
+use super::encrypt::{Aes256Gcm, AES};
+use std::io;
+
+/// Encrypts individual values with a key of their own, for data that needs one on top of
+/// the encryption of the store files.
+#[derive(Clone)]
+pub struct Cipher {
+    aes: AES<Aes256Gcm>,
+}
+
+impl Cipher {
+    /// Cipher for a 32 byte `key`, such as one from [`MasterKey::derive`].
+    ///
+    /// [`MasterKey::derive`]: crate::master_key::MasterKey::derive
+    pub fn new(key: &[u8]) -> Self {
+        Self { aes: AES::new(key) }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        self.aes
+            .encrypt(plaintext, None)
+            .map_err(|_| io::Error::other("Failed to encrypt value"))
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        self.aes
+            .decrypt(ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt value"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::master_key::MasterKey;
+
+    #[test]
+    fn test_cipher() {
+        let key = MasterKey::generate();
+        let cipher = Cipher::new(&key.derive(b"test"));
+        let sealed = cipher.encrypt(b"secret").unwrap();
+        assert_ne!(sealed, b"secret");
+        assert_eq!(
+            Cipher::new(&key.derive(b"test")).decrypt(&sealed).unwrap(),
+            b"secret"
+        );
+        assert!(Cipher::new(&key.derive(b"other")).decrypt(&sealed).is_err());
+        let other = MasterKey::generate();
+        assert!(Cipher::new(&other.derive(b"test"))
+            .decrypt(&sealed)
+            .is_err());
+    }
+}
@@ -1,7 +1,10 @@
 use super::file::File;
 use super::handlers::FolderHandler;
+use super::lock::{FileLock, LockMode};
 use super::logs::LogsManager;
-use std::path::PathBuf;
+use super::master_key::MasterKey;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Directory {
@@ -9,22 +12,81 @@ pub struct Directory {
     subfolders: Vec<FolderHandler>,
     files: Vec<File>,
     logger: LogsManager,
+    master_key: MasterKey,
+    /// Whether the master key was created when opening rather than found in the store.
+    new_key: bool,
+    read_only: bool,
 }
 
+const MASTER_KEY_FILE: &str = "master.key";
+const DATA_LOCK_FILE: &str = "data.lock";
+
 impl Directory {
     pub fn new(path: impl Into<PathBuf>) -> Self {
         let path = path.into();
+        let handler = FolderHandler::new(path.clone()).unwrap();
+        handler.create().unwrap();
+        let key_path = path.join(MASTER_KEY_FILE);
+        let fresh = !key_path.exists();
+        let master_key = MasterKey::load_or_create(&key_path).unwrap();
+        let logger = LogsManager::new(path.clone(), &master_key).unwrap();
+        if fresh {
+            // Logs from before the store had a master key cannot be read with it.
+            logger.set_aside().unwrap();
+        }
         let mut dir = Directory {
-            handler: FolderHandler::new(path.clone()).unwrap(),
+            handler,
             subfolders: Vec::new(),
             files: Vec::new(),
-            logger: LogsManager::new(path.clone()).unwrap(),
+            logger,
+            master_key,
+            new_key: fresh,
+            read_only: false,
         };
-        dir.create().unwrap();
         dir.subfolders = Self::generate_folders(&path);
         dir.files = Self::generate_files(&path);
         dir
     }
+
+    /// Opens an existing store without creating or changing anything in it. A store that
+    /// has no master key yet, because it has not been opened for writing since keys moved
+    /// to it, has to be opened for writing once first: anything logged without its key
+    /// could never be read.
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let required = [
+            PathBuf::from("logs"),
+            PathBuf::from("objects").join("objects.bin"),
+            PathBuf::from("metadata.zewos"),
+            PathBuf::from("config.zewos"),
+        ];
+        if !path.is_dir() || required.iter().any(|entry| !path.join(entry).exists()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No store at {}", path.display()),
+            ));
+        }
+        let key_path = path.join(MASTER_KEY_FILE);
+        if !key_path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "No master key at {}, open the store for writing first",
+                    key_path.display()
+                ),
+            ));
+        }
+        let master_key = MasterKey::load(&key_path)?;
+        Ok(Directory {
+            handler: FolderHandler::new(path.clone())?,
+            subfolders: Self::generate_folders(&path),
+            files: Self::generate_files(&path),
+            logger: LogsManager::new(path, &master_key)?,
+            master_key,
+            new_key: false,
+            read_only: true,
+        })
+    }
     fn generate_folders(origin: &PathBuf) -> Vec<FolderHandler> {
         ["objects"]
             .iter()
@@ -32,15 +94,20 @@ impl Directory {
             .collect()
     }
     fn generate_files(origin: &PathBuf) -> Vec<File> {
-        let objects = PathBuf::from("objects").join("objects.bin");
+        Self::file_paths(origin)
+            .into_iter()
+            .map(File::new)
+            .collect()
+    }
+
+    /// Paths of the object, metadata and config files of the store at `origin`, whether
+    /// they exist or not.
+    pub fn file_paths(origin: &Path) -> [PathBuf; 3] {
         [
-            objects,
-            PathBuf::from("metadata.zewos"),
-            PathBuf::from("config.zewos"),
+            origin.join("objects").join("objects.bin"),
+            origin.join("metadata.zewos"),
+            origin.join("config.zewos"),
         ]
-        .iter()
-        .map(|entry| File::new(origin.join(entry)))
-        .collect()
     }
 
     pub fn get_handler(&self) -> &FolderHandler {
@@ -50,6 +117,16 @@ impl Directory {
         self.logger.clone()
     }
 
+    pub fn master_key(&self) -> &MasterKey {
+        &self.master_key
+    }
+
+    /// Whether the store had no master key until this directory was opened, so nothing in
+    /// it can have been signed or encrypted with that key yet.
+    pub fn has_new_key(&self) -> bool {
+        self.new_key
+    }
+
     pub fn get_files(&self) -> &[File] {
         &self.files
     }
@@ -65,6 +142,37 @@ impl Directory {
         self.files.get(2).unwrap()
     }
 
+    /// Lock held by the one process allowed to write to the store.
+    pub fn writer_lock(&self, timeout: Option<Duration>) -> std::io::Result<FileLock> {
+        FileLock::acquire(
+            &self.handler.path.join("lock"),
+            LockMode::Exclusive,
+            timeout,
+        )
+    }
+
+    /// Short-lived lock taken around reading (shared) or rewriting (exclusive) the store files.
+    ///
+    /// A directory from [`Directory::open`] does not create the lock file, and gets `None`
+    /// if no writer has created it yet.
+    pub fn data_lock(&self, mode: LockMode) -> std::io::Result<Option<FileLock>> {
+        if self.read_only {
+            Self::existing_data_lock(&self.handler.path, mode)
+        } else {
+            FileLock::wait(&self.handler.path.join(DATA_LOCK_FILE), mode).map(Some)
+        }
+    }
+
+    /// [`Directory::data_lock`] of the store at `origin`, taken without opening the store
+    /// or creating the lock file.
+    pub fn existing_data_lock(origin: &Path, mode: LockMode) -> std::io::Result<Option<FileLock>> {
+        FileLock::wait_existing(&origin.join(DATA_LOCK_FILE), mode)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn exists(&self) -> bool {
         self.handler.exists()
     }
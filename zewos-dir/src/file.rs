@@ -69,6 +69,14 @@ impl File {
     pub fn path(&self) -> &PathBuf {
         &self.handler.path
     }
+
+    pub fn check_permissions(&self) -> io::Result<bool> {
+        self.handler.check_permissions()
+    }
+
+    pub fn fix_permissions(&self) -> io::Result<()> {
+        self.handler.fix_permissions()
+    }
 }
 
 #[cfg(test)]
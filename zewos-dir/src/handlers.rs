@@ -43,7 +43,13 @@ impl FileHandler {
         let mut file = File::open(&self.path)?;
         let mut contents = Vec::new();
         file.read_to_end(&mut contents)?;
-        let contents = self.aes.decrypt(&contents).unwrap();
+        if contents.is_empty() {
+            return Ok(contents);
+        }
+        let contents = self
+            .aes
+            .decrypt(&contents)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt file"))?;
 
         Ok(contents)
     }
@@ -64,6 +70,16 @@ impl FileHandler {
         let mut file = File::create(&self.path)?;
         file.write_all(contents)
     }
+
+    pub fn check_permissions(&self) -> io::Result<bool> {
+        self.permissions
+            .check_file_permissions(self.path.to_str().unwrap_or_default())
+    }
+
+    pub fn fix_permissions(&self) -> io::Result<()> {
+        self.permissions
+            .set_file_permissions(self.path.to_str().unwrap_or_default())
+    }
 }
 #[derive(Clone)]
 pub struct FolderHandler {
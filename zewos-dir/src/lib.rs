@@ -1,5 +1,10 @@
+pub mod archive;
+pub mod cipher;
 pub mod dir;
 mod encrypt;
 pub mod file;
 pub mod handlers;
+pub mod lock;
 pub mod logs;
+pub mod master_key;
+pub mod sink;
@@ -0,0 +1,109 @@
+use std::fs::{self, OpenOptions, TryLockError};
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Any number of holders at once, as long as nobody holds the lock exclusively.
+    Shared,
+    Exclusive,
+}
+
+/// Advisory lock on a file, released when dropped.
+///
+/// Acquiring fails with [`io::ErrorKind::WouldBlock`] if the lock is still held elsewhere
+/// once `timeout` has passed; without a timeout it fails right away.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: fs::File,
+}
+
+impl FileLock {
+    pub fn acquire(path: &Path, mode: LockMode, timeout: Option<Duration>) -> io::Result<Self> {
+        let file = open(path)?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let result = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+            match result {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::Error(e)) => return Err(e),
+                Err(TryLockError::WouldBlock) => {
+                    if deadline.is_none_or(|deadline| Instant::now() >= deadline) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::WouldBlock,
+                            format!("{} is locked", path.display()),
+                        ));
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+            }
+        }
+    }
+
+    /// Blocks until the lock is available. Meant for locks that are only held briefly.
+    pub fn wait(path: &Path, mode: LockMode) -> io::Result<Self> {
+        let file = open(path)?;
+        match mode {
+            LockMode::Shared => file.lock_shared()?,
+            LockMode::Exclusive => file.lock()?,
+        }
+        Ok(Self { _file: file })
+    }
+
+    /// Like [`FileLock::wait`], but never creates the lock file. Returns `None` if it does
+    /// not exist.
+    pub fn wait_existing(path: &Path, mode: LockMode) -> io::Result<Option<Self>> {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match mode {
+            LockMode::Shared => file.lock_shared()?,
+            LockMode::Exclusive => file.lock()?,
+        }
+        Ok(Some(Self { _file: file }))
+    }
+}
+
+fn open(path: &Path) -> io::Result<fs::File> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("lock");
+
+        let shared = FileLock::acquire(&path, LockMode::Shared, None).unwrap();
+        let _other = FileLock::acquire(&path, LockMode::Shared, None).unwrap();
+        let busy = FileLock::acquire(&path, LockMode::Exclusive, None).unwrap_err();
+        assert_eq!(busy.kind(), io::ErrorKind::WouldBlock);
+        drop(shared);
+        drop(_other);
+
+        let exclusive = FileLock::acquire(&path, LockMode::Exclusive, None).unwrap();
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(exclusive);
+        });
+        FileLock::acquire(&path, LockMode::Shared, Some(Duration::from_secs(5))).unwrap();
+        release.join().unwrap();
+    }
+}
@@ -1,88 +1,986 @@
+use super::encrypt::{Aes256Gcm, AES};
+use super::master_key::MasterKey;
 use super::{file::File, handlers::FolderHandler};
-use chrono::Local;
-use std::io;
-use std::path::PathBuf;
-pub use zewos_core::logging::Log;
-use zewos_core::logging::LogFileStruct;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use zewos_core::hash::{FromHex, Sha256, ToHex};
+use zewos_core::logging::{ChainHead, LogFileStruct, LogLine};
+pub use zewos_core::logging::{ErrorKind, Log, Outcome};
+use zewos_core::signing::Keypair;
+
+const DEFAULT_SIGNATURE_INTERVAL: usize = 64;
+const COMPRESSION_LEVEL: i32 = 3;
+/// Signed chain head preceding the oldest retained record, written once older files are
+/// pruned so the chain can still be verified from there.
+const ANCHOR_FILE: &str = "chain.anchor";
+/// Authenticated copy of the latest chain head. It is kept outside the log folder, so
+/// records cut off the end of the chain are noticed even though the chain itself still
+/// verifies.
+const HEAD_FILE: &str = "audit.head";
+
+/// Rotation and retention of session files. Everything is off by default, which keeps one
+/// file per session forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LogsConfig {
+    /// Start a new file once the current one reaches this many bytes.
+    pub max_file_size: Option<u64>,
+    /// Start a new file when the local date changes.
+    pub rotate_daily: bool,
+    /// Keep at most this many files, deleting the oldest ones.
+    pub max_files: Option<usize>,
+    /// Delete files last written longer ago than this.
+    pub max_age: Option<Duration>,
+    /// Compress rotated files with zstd.
+    pub compress_rotated: bool,
+}
+
+impl LogsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    pub fn with_rotate_daily(mut self, rotate_daily: bool) -> Self {
+        self.rotate_daily = rotate_daily;
+        self
+    }
+
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files.max(1));
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_compress_rotated(mut self, compress_rotated: bool) -> Self {
+        self.compress_rotated = compress_rotated;
+        self
+    }
+}
+
+/// How key names appear in audit log entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyRedaction {
+    /// The key is logged as-is.
+    Plain,
+    /// The key is replaced by a keyed HMAC, so entries about the same key can still be
+    /// correlated without revealing its name.
+    #[default]
+    Hmac,
+    /// The key is omitted entirely.
+    Hidden,
+}
+
+/// Turns keys into the labels [`LogsManager`] records them under, without going through
+/// the manager.
+#[derive(Clone)]
+pub struct KeyLabeler {
+    key_redaction: KeyRedaction,
+    redaction_key: Vec<u8>,
+}
+
+impl KeyLabeler {
+    /// How `key` should be referred to in a log entry under the configured redaction mode.
+    pub fn label(&self, key: &[u8]) -> String {
+        match self.key_redaction {
+            KeyRedaction::Plain => String::from_utf8_lossy(key).into_owned(),
+            KeyRedaction::Hmac => format!(
+                "hmac-{}",
+                Sha256::keyed(&self.redaction_key, key).encode_hex::<String>()
+            ),
+            KeyRedaction::Hidden => "redacted".to_string(),
+        }
+    }
+
+    pub fn key_redaction(&self) -> KeyRedaction {
+        self.key_redaction
+    }
+}
 
 #[derive(Clone)]
 pub struct LogsManager {
     handler: FolderHandler,
     logs: Vec<LogFile>,
     current_log: Option<LogFile>,
+    keypair: Keypair,
+    signature_interval: usize,
+    aes: AES<Aes256Gcm>,
+    labeler: KeyLabeler,
+    head_key: Vec<u8>,
+    head_file: PathBuf,
+    session_id: String,
+    config: LogsConfig,
+}
+
+/// Filter for [`LogsManager::query`]. Every criterion that is set must match.
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    operation: Option<String>,
+    key: Option<Vec<u8>>,
+    outcome: Option<Outcome>,
+    error_id: Option<String>,
+}
+
+impl LogQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+    pub fn with_until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+    pub fn with_operation(mut self, operation: impl Into<String>) -> Self {
+        self.operation = Some(operation.into());
+        self
+    }
+    /// Matches records about `key`. The key is redacted the same way the manager redacts
+    /// it when logging, so records written under another redaction mode will not match.
+    pub fn with_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+    pub fn with_outcome(mut self, outcome: Outcome) -> Self {
+        self.outcome = Some(outcome);
+        self
+    }
+    pub fn with_error_id(mut self, error_id: impl Into<String>) -> Self {
+        self.error_id = Some(error_id.into());
+        self
+    }
+}
+
+/// Records matching a [`LogQuery`], read lazily one line at a time across session files.
+pub struct LogIter {
+    files: std::vec::IntoIter<PathBuf>,
+    lines: Option<io::Lines<Box<dyn BufRead>>>,
+    aes: AES<Aes256Gcm>,
+    query: LogQuery,
+    key_id: Option<String>,
+}
+
+impl LogIter {
+    fn matches(&self, log: &Log) -> bool {
+        self.query
+            .since
+            .is_none_or(|since| *log.timestamp() >= since)
+            && self
+                .query
+                .until
+                .is_none_or(|until| *log.timestamp() < until)
+            && self
+                .query
+                .operation
+                .as_deref()
+                .is_none_or(|operation| log.operation() == operation)
+            && self
+                .key_id
+                .as_deref()
+                .is_none_or(|key_id| log.key_id() == Some(key_id))
+            && self
+                .query
+                .outcome
+                .is_none_or(|outcome| log.outcome() == outcome)
+            && self
+                .query
+                .error_id
+                .as_deref()
+                .is_none_or(|error_id| log.error_id() == Some(error_id))
+    }
+
+    fn decode(&self, line: &str) -> Option<Log> {
+        let plaintext = self.aes.decrypt(&Vec::from_hex(line).ok()?).ok()?;
+        match LogLine::parse(std::str::from_utf8(&plaintext).ok()?)? {
+            LogLine::Record { content, .. } => Log::parse(&content),
+            LogLine::Signature { .. } => None,
+        }
+    }
+}
+
+impl Iterator for LogIter {
+    type Item = io::Result<Log>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(lines) = self.lines.as_mut() else {
+                let path = self.files.next()?;
+                match open_session(&path) {
+                    Ok(reader) => self.lines = Some(reader.lines()),
+                    Err(e) => return Some(Err(e)),
+                }
+                continue;
+            };
+            match lines.next() {
+                None => self.lines = None,
+                Some(Err(e)) => return Some(Err(e)),
+                // Signatures and lines that fail to decode are skipped; `verify_chain`
+                // is the place to report them.
+                Some(Ok(line)) => match self.decode(&line) {
+                    Some(log) if self.matches(&log) => return Some(Ok(log)),
+                    _ => {}
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainErrorKind {
+    /// The line could not be parsed as a record or signature.
+    Malformed,
+    /// The record hash does not match its contents.
+    Tampered,
+    /// Records are missing between the previous record and this one.
+    Missing { expected_seq: u64, found_seq: u64 },
+    /// The record does not link to the hash of the previous record.
+    BrokenLink,
+    /// The signature over the chain head does not verify.
+    BadSignature,
+    /// Records up to the head recorded outside the log folder are missing from the end of
+    /// the chain.
+    Truncated { expected_seq: u64, found_seq: u64 },
+    /// The chain has records but the head recorded outside the log folder is missing or
+    /// fails authentication.
+    BadHead,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub kind: ChainErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainReport {
+    pub records: u64,
+    pub signatures: u64,
+    pub first_error: Option<ChainError>,
+}
+
+impl ChainReport {
+    pub fn is_valid(&self) -> bool {
+        self.first_error.is_none()
+    }
 }
 
 impl LogsManager {
-    pub(crate) fn new(path: PathBuf) -> io::Result<Self> {
-        let path = path.join("logs");
+    /// Logs of the store at `path`, in its `logs` folder, with the chain head recorded in
+    /// the store folder itself.
+    pub(crate) fn new(path: PathBuf, key: &MasterKey) -> io::Result<Self> {
+        Ok(Self::in_folder(path.join("logs"), key)?.with_head_file(path.join(HEAD_FILE)))
+    }
+
+    /// Logs kept directly in `path` rather than in a store's `logs` folder, created if
+    /// missing. Every key is derived from `key`, and the chain head is recorded next to the
+    /// folder in `<path>.head` unless set with [`LogsManager::with_head_file`].
+    pub fn in_folder(path: PathBuf, key: &MasterKey) -> io::Result<Self> {
+        let mut head_file = path.clone().into_os_string();
+        head_file.push(".head");
         let handler = FolderHandler::new(path)?;
         Ok(LogsManager {
             handler,
             logs: Vec::new(),
             current_log: None,
+            keypair: key.keypair(b"zewos-audit-signing")?,
+            signature_interval: DEFAULT_SIGNATURE_INTERVAL,
+            aes: AES::new(key.derive(b"zewos-log-encryption").as_slice()),
+            labeler: KeyLabeler {
+                key_redaction: KeyRedaction::default(),
+                redaction_key: key.derive(b"zewos-log-redaction").to_vec(),
+            },
+            head_key: key.derive(b"zewos-log-head").to_vec(),
+            head_file: PathBuf::from(head_file),
+            session_id: String::new(),
+            config: LogsConfig::default(),
         })
     }
 
+    /// Records the chain head in `path`, which should be outside the log folder.
+    pub fn with_head_file(mut self, path: PathBuf) -> Self {
+        self.head_file = path;
+        self
+    }
+
+    pub fn with_config(mut self, config: LogsConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_key_redaction(mut self, key_redaction: KeyRedaction) -> Self {
+        self.labeler.key_redaction = key_redaction;
+        self
+    }
+
+    pub fn config(&self) -> LogsConfig {
+        self.config
+    }
+
+    pub fn key_redaction(&self) -> KeyRedaction {
+        self.labeler.key_redaction
+    }
+
+    pub fn key_labeler(&self) -> KeyLabeler {
+        self.labeler.clone()
+    }
+
+    /// Whether a session is open, without which [`LogsManager::add_log`] drops records.
+    pub fn in_session(&self) -> bool {
+        self.current_log.is_some()
+    }
+
+    /// How `key` should be referred to in a log entry under the configured redaction mode.
+    pub fn key_label(&self, key: &[u8]) -> String {
+        self.labeler.label(key)
+    }
+
+    /// Signs the chain head every `interval` records.
+    pub fn with_signature_interval(mut self, interval: usize) -> Self {
+        self.signature_interval = interval.max(1);
+        self
+    }
+
     pub fn start_session(&mut self) -> io::Result<()> {
-        let file_name = format!("{}.zewos", Local::now().format("%Y-%m-%d_%H-%M-%S"));
-        self.current_log = Some(LogFile::new(self.handler.path.join(file_name))?);
-        Ok(())
+        let head = self.chain_head()?;
+        self.session_id = uuid::Uuid::new_v4().to_string();
+        self.open_file(head)?;
+        self.prune()
     }
 
     pub fn end_session(&mut self) -> io::Result<()> {
-        if let Some(log) = self.current_log.take() {
+        if let Some(mut log) = self.current_log.take() {
+            if log.unsigned > 0 {
+                log.sign(&self.keypair);
+            }
             log.save()?;
         }
         Ok(())
     }
 
-    pub fn add_log(
-        &mut self,
-        details: &str,
-        action: &str,
-        additional_info: &str,
-    ) -> io::Result<()> {
-        if self.current_log.is_none() {
+    pub fn add_log(&mut self, log: Log) -> io::Result<()> {
+        let entry = log.with_session_id(self.session_id.clone());
+        let Some(log) = self.current_log.as_mut() else {
             // Session hasn't activated. Either start it or you have logs turned off.
             return Ok(());
+        };
+        log.add_log(entry)?;
+        if log.unsigned >= self.signature_interval {
+            log.sign(&self.keypair);
+        }
+        log.save()?;
+        let head = log.logs.head();
+        self.write_head(head)?;
+        if self.should_rotate()? {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn open_file(&mut self, head: ChainHead) -> io::Result<()> {
+        let file_name = format!("{}.zewos", Local::now().format("%Y-%m-%d_%H-%M-%S_%6f"));
+        self.current_log = Some(LogFile::new(
+            self.handler.path.join(file_name),
+            head,
+            self.aes.clone(),
+        )?);
+        Ok(())
+    }
+
+    fn should_rotate(&self) -> io::Result<bool> {
+        let Some(log) = self.current_log.as_ref() else {
+            return Ok(false);
+        };
+        let oversized = match self.config.max_file_size {
+            Some(max) => log.file.size()? >= max,
+            None => false,
+        };
+        Ok(oversized || (self.config.rotate_daily && log.opened != Local::now().date_naive()))
+    }
+
+    /// Seals the current file and continues the session, and its chain, in a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        let Some(mut log) = self.current_log.take() else {
+            return Ok(());
+        };
+        if log.unsigned > 0 {
+            log.sign(&self.keypair);
+        }
+        log.save()?;
+        if self.config.compress_rotated {
+            let path = log.file.path();
+            let compressed = zstd::encode_all(fs::read(path)?.as_slice(), COMPRESSION_LEVEL)?;
+            let mut target = path.clone().into_os_string();
+            target.push(".zst");
+            File::new(PathBuf::from(target)).write_no_encrypt(&compressed)?;
+            log.file.delete()?;
+        }
+        self.open_file(log.logs.head())?;
+        self.prune()
+    }
+
+    /// Applies the retention settings to every file but the one being written.
+    fn prune(&self) -> io::Result<()> {
+        let current = self.current_log.as_ref().map(|log| log.file.path().clone());
+        let mut files: Vec<PathBuf> = self
+            .session_files()?
+            .into_iter()
+            .filter(|path| Some(path) != current.as_ref())
+            .collect();
+        let retained = self
+            .config
+            .max_files
+            .map_or(files.len(), |max| max.saturating_sub(1));
+        let mut expired = files.len().saturating_sub(retained);
+        if let Some(max_age) = self.config.max_age {
+            let cutoff = SystemTime::now() - max_age;
+            while expired < files.len() && fs::metadata(&files[expired])?.modified()? < cutoff {
+                expired += 1;
+            }
+        }
+        if expired == 0 {
+            return Ok(());
+        }
+
+        let oldest = files
+            .get(expired)
+            .map(|path| self.first_head(path))
+            .transpose()?
+            .flatten();
+        let anchor = match (oldest, self.current_log.as_ref()) {
+            (Some(head), _) => head,
+            (None, Some(log)) => log.start,
+            (None, None) => return Ok(()),
+        };
+        let signature = self.keypair.sign(&anchor.signing_message());
+        let line = LogLine::Signature {
+            head: anchor,
+            signature,
+        };
+        File::new(self.handler.path.join(ANCHOR_FILE))
+            .write_no_encrypt(format!("{}\n", line.serialize()).as_bytes())?;
+        for path in files.drain(..expired) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Chain head the first record of `path` builds on.
+    fn first_head(&self, path: &Path) -> io::Result<Option<ChainHead>> {
+        for line in open_session(path)?.lines() {
+            if let Some(LogLine::Record { seq, prev_hash, .. }) = self
+                .decrypt_line(&line?)
+                .as_deref()
+                .and_then(LogLine::parse)
+            {
+                return Ok(Some(ChainHead {
+                    seq,
+                    hash: prev_hash,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Where verification starts: the genesis head, or the signed anchor left behind when
+    /// older files were pruned.
+    fn anchor(&self) -> io::Result<Result<ChainHead, ChainError>> {
+        let path = self.handler.path.join(ANCHOR_FILE);
+        if !path.exists() {
+            return Ok(Ok(ChainHead::genesis()));
+        }
+        let contents = fs::read_to_string(&path)?;
+        match LogLine::parse(contents.trim_end()) {
+            Some(LogLine::Signature { head, signature })
+                if self
+                    .keypair
+                    .verify(&head.signing_message(), &signature)
+                    .is_ok() =>
+            {
+                Ok(Ok(head))
+            }
+            _ => Ok(Err(ChainError {
+                file: path,
+                line: 1,
+                kind: ChainErrorKind::BadSignature,
+            })),
+        }
+    }
+
+    fn head_mac(&self, head: &ChainHead) -> Sha256 {
+        Sha256::keyed(&self.head_key, &head.signing_message())
+    }
+
+    /// Replaces the recorded chain head, through a rename so it is never seen half written.
+    fn write_head(&self, head: ChainHead) -> io::Result<()> {
+        let contents = format!(
+            "{}|{}|{}\n",
+            head.seq,
+            head.hash.encode_hex::<String>(),
+            self.head_mac(&head).encode_hex::<String>()
+        );
+        let mut tmp = self.head_file.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.head_file)
+    }
+
+    /// The recorded chain head, `None` if there is none and `Err` if it does not
+    /// authenticate.
+    fn read_head(&self) -> io::Result<Option<Result<ChainHead, ()>>> {
+        let contents = match fs::read_to_string(&self.head_file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut fields = contents.trim_end().split('|');
+        let head = (|| {
+            let seq = fields.next()?.parse().ok()?;
+            let hash = Sha256::from_hex(fields.next()?).ok()?;
+            let mac = Sha256::from_hex(fields.next()?).ok()?;
+            let head = ChainHead { seq, hash };
+            (self.head_mac(&head) == mac).then_some(head)
+        })();
+        Ok(Some(head.ok_or(())))
+    }
+
+    /// Walks every session file in chronological order and checks that records form an
+    /// unbroken hash chain, that every signature over the chain head verifies, and that the
+    /// chain reaches the head recorded outside the log folder.
+    pub fn verify_chain(&self) -> io::Result<ChainReport> {
+        let mut report = ChainReport {
+            records: 0,
+            signatures: 0,
+            first_error: None,
+        };
+        let mut head = match self.anchor()? {
+            Ok(head) => head,
+            Err(error) => {
+                report.first_error = Some(error);
+                return Ok(report);
+            }
+        };
+        let recorded = self.read_head()?;
+        let mut reached = recorded.is_none_or(|recorded| recorded == Ok(head));
+        for path in self.session_files()? {
+            for (index, line) in open_session(&path)?.lines().enumerate() {
+                let line = self.decrypt_line(&line?);
+                let error = |kind| ChainError {
+                    file: path.clone(),
+                    line: index + 1,
+                    kind,
+                };
+                match line.as_deref().and_then(LogLine::parse) {
+                    None => report.first_error = Some(error(ChainErrorKind::Malformed)),
+                    Some(LogLine::Record {
+                        content,
+                        seq,
+                        prev_hash,
+                        hash,
+                    }) => {
+                        if seq != head.seq {
+                            report.first_error = Some(error(ChainErrorKind::Missing {
+                                expected_seq: head.seq,
+                                found_seq: seq,
+                            }));
+                        } else if prev_hash != head.hash {
+                            report.first_error = Some(error(ChainErrorKind::BrokenLink));
+                        } else if head.next(&content).hash != hash {
+                            report.first_error = Some(error(ChainErrorKind::Tampered));
+                        } else {
+                            head = head.next(&content);
+                            report.records += 1;
+                            reached |= recorded == Some(Ok(head));
+                        }
+                    }
+                    Some(LogLine::Signature {
+                        head: signed,
+                        signature,
+                    }) => {
+                        if signed != head
+                            || self
+                                .keypair
+                                .verify(&signed.signing_message(), &signature)
+                                .is_err()
+                        {
+                            report.first_error = Some(error(ChainErrorKind::BadSignature));
+                        } else {
+                            report.signatures += 1;
+                        }
+                    }
+                }
+                if report.first_error.is_some() {
+                    return Ok(report);
+                }
+            }
+        }
+        let error = |kind| ChainError {
+            file: self.head_file.clone(),
+            line: 1,
+            kind,
+        };
+        match recorded {
+            None if report.records > 0 => report.first_error = Some(error(ChainErrorKind::BadHead)),
+            Some(Err(())) => report.first_error = Some(error(ChainErrorKind::BadHead)),
+            Some(Ok(recorded)) if !reached => {
+                report.first_error = Some(error(if recorded.seq > head.seq {
+                    ChainErrorKind::Truncated {
+                        expected_seq: recorded.seq,
+                        found_seq: head.seq,
+                    }
+                } else {
+                    ChainErrorKind::BrokenLink
+                }))
+            }
+            _ => {}
         }
-        if let Some(log) = self.current_log.as_mut() {
-            log.add_log(details, action, additional_info)?;
+        Ok(report)
+    }
+
+    /// Streams the records of every session, oldest first, that match `query`.
+    pub fn query(&self, query: LogQuery) -> io::Result<LogIter> {
+        let key_id = query.key.as_deref().map(|key| self.key_label(key));
+        Ok(LogIter {
+            files: self.session_files()?.into_iter(),
+            lines: None,
+            aes: self.aes.clone(),
+            query,
+            key_id,
+        })
+    }
+
+    /// Moves the existing log files into a `legacy` subfolder, for logs written under keys
+    /// the store no longer uses. They are kept for reference but no longer verified.
+    pub(crate) fn set_aside(&self) -> io::Result<()> {
+        let mut files = self.session_files()?;
+        files.push(self.handler.path.join(ANCHOR_FILE));
+        let legacy = self.handler.path.join("legacy");
+        for path in files.into_iter().filter(|path| path.exists()) {
+            fs::create_dir_all(&legacy)?;
+            fs::rename(&path, legacy.join(path.file_name().unwrap_or_default()))?;
         }
-        self.current_log.as_ref().unwrap().save()?;
         Ok(())
     }
+
+    fn session_files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = self
+            .handler
+            .list_contents()?
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.ends_with(".zewos") || name.ends_with(".zewos.zst"))
+            })
+            .collect();
+        // Session files are named after their start time, so name order is chronological.
+        files.sort();
+        Ok(files)
+    }
+
+    fn chain_head(&self) -> io::Result<ChainHead> {
+        for path in self.session_files()?.iter().rev() {
+            let mut last = None;
+            for line in open_session(path)?.lines() {
+                if let Some(LogLine::Record { seq, hash, .. }) = self
+                    .decrypt_line(&line?)
+                    .as_deref()
+                    .and_then(LogLine::parse)
+                {
+                    last = Some(ChainHead { seq: seq + 1, hash });
+                }
+            }
+            if let Some(head) = last {
+                return Ok(head);
+            }
+        }
+        match self.anchor()? {
+            Ok(head) => Ok(head),
+            Err(_) => Ok(ChainHead::genesis()),
+        }
+    }
+
+    fn decrypt_line(&self, line: &str) -> Option<String> {
+        let plaintext = self.aes.decrypt(&Vec::from_hex(line).ok()?).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
 }
+/// Opens a session file for line-by-line reading, decompressing rotated files.
+fn open_session(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = fs::File::open(path)?;
+    if path.extension().is_some_and(|ext| ext == "zst") {
+        let mut contents = Vec::new();
+        io::Read::read_to_end(&mut BufReader::new(file), &mut contents)?;
+        return Ok(Box::new(Cursor::new(zstd::decode_all(
+            contents.as_slice(),
+        )?)));
+    }
+    Ok(Box::new(BufReader::new(file)))
+}
+
 #[derive(Clone)]
 pub struct LogFile {
     file: File,
     logs: LogFileStruct,
+    unsigned: usize,
+    aes: AES<Aes256Gcm>,
+    start: ChainHead,
+    opened: NaiveDate,
 }
 
 impl LogFile {
-    pub fn new(path: PathBuf) -> io::Result<Self> {
+    pub fn new(path: PathBuf, head: ChainHead, aes: AES<Aes256Gcm>) -> io::Result<Self> {
         Ok(LogFile {
             file: File::new(path),
-            logs: LogFileStruct::new(),
+            logs: LogFileStruct::with_head(head),
+            unsigned: 0,
+            aes,
+            start: head,
+            opened: Local::now().date_naive(),
         })
     }
 
-    pub fn add_log(
-        &mut self,
-        details: &str,
-        action: &str,
-        additional_info: &str,
-    ) -> io::Result<()> {
-        let _ = self.logs.add(Log::new(
-            details.to_string(),
-            action.to_string(),
-            additional_info.to_string(),
-        ));
+    pub fn add_log(&mut self, log: Log) -> io::Result<()> {
+        let _ = self.logs.add(log);
+        self.unsigned += 1;
         Ok(())
     }
 
-    pub fn save(&self) -> io::Result<()> {
-        let serialized = self.logs.serialize();
-        self.file.write_no_encrypt(serialized.as_bytes())
+    fn sign(&mut self, keypair: &Keypair) {
+        let signature = keypair.sign(&self.logs.head().signing_message());
+        self.logs.add_signature(signature);
+        self.unsigned = 0;
+    }
+
+    /// Appends the lines added since the last save, each encrypted on its own so a damaged
+    /// line does not take the rest of the file with it.
+    pub fn save(&mut self) -> io::Result<()> {
+        let mut serialized = String::new();
+        for line in self.logs.take_lines() {
+            let encrypted = self
+                .aes
+                .encrypt(line.serialize().as_bytes(), None)
+                .map_err(|_| io::Error::other("Failed to encrypt log line"))?;
+            serialized.push_str(&encrypted.encode_hex::<String>());
+            serialized.push('\n');
+        }
+        self.file.append(&serialized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn manager(path: &std::path::Path) -> LogsManager {
+        let key = MasterKey::load_or_create(&path.join("master.key")).unwrap();
+        LogsManager::new(path.to_path_buf(), &key).unwrap()
+    }
+
+    fn session(path: &std::path::Path, logs: usize) -> LogsManager {
+        let mut manager = manager(path).with_signature_interval(2);
+        manager.start_session().unwrap();
+        for i in 0..logs {
+            manager
+                .add_log(Log::new("get", Outcome::Success).with_key_id(format!("key-{}", i)))
+                .unwrap();
+        }
+        manager
+    }
+
+    #[test]
+    fn test_chain_spans_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        session(temp_dir.path(), 3).end_session().unwrap();
+        let manager = session(temp_dir.path(), 2);
+
+        let report = manager.verify_chain().unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.records, 5);
+        assert_eq!(report.signatures, 3);
+    }
+
+    #[test]
+    fn test_chain_detects_tampering() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = session(temp_dir.path(), 3);
+        let path = manager.session_files().unwrap().remove(0);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let tampered: Vec<String> = contents
+            .lines()
+            .map(|line| {
+                let line = manager
+                    .decrypt_line(line)
+                    .unwrap()
+                    .replacen("key-1", "key-9", 1);
+                manager
+                    .aes
+                    .encrypt(line.as_bytes(), None)
+                    .unwrap()
+                    .encode_hex()
+            })
+            .collect();
+        std::fs::write(&path, tampered.join("\n")).unwrap();
+
+        let error = manager.verify_chain().unwrap().first_error.unwrap();
+        assert_eq!(error.file, path);
+        assert_eq!(error.line, 2);
+        assert_eq!(error.kind, ChainErrorKind::Tampered);
+    }
+
+    #[test]
+    fn test_logs_are_encrypted_and_redacted() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = session(temp_dir.path(), 0);
+        let label = manager.key_label(b"secret-name");
+        assert_eq!(label, manager.key_label(b"secret-name"));
+        assert_ne!(label, manager.key_label(b"other-name"));
+        assert!(!label.contains("secret-name"));
+        manager
+            .add_log(Log::new("get", Outcome::Success).with_key_id(label.clone()))
+            .unwrap();
+
+        let path = manager.session_files().unwrap().remove(0);
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert!(!contents.contains("operation"));
+        assert!(!contents.contains(&label));
+        assert!(manager.verify_chain().unwrap().is_valid());
+
+        let other = TempDir::new().unwrap();
+        assert_ne!(label, self::manager(other.path()).key_label(b"secret-name"));
+
+        let manager = manager.with_key_redaction(KeyRedaction::Hidden);
+        assert_eq!(manager.key_label(b"secret-name"), "redacted");
+    }
+
+    #[test]
+    fn test_query() {
+        let temp_dir = TempDir::new().unwrap();
+        session(temp_dir.path(), 2).end_session().unwrap();
+        let mut manager = session(temp_dir.path(), 0);
+        let start = Utc::now();
+        let label = manager.key_label(b"secret");
+        manager
+            .add_log(Log::new("get", Outcome::Failure).with_key_id(label.clone()))
+            .unwrap();
+        manager
+            .add_log(Log::new("insert", Outcome::Success).with_key_id(label))
+            .unwrap();
+
+        let all: Vec<Log> = manager
+            .query(LogQuery::new())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(all.len(), 4);
+
+        let reads: Vec<Log> = manager
+            .query(LogQuery::new().with_operation("get").with_key("secret"))
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(reads.len(), 1);
+        assert_eq!(reads[0].outcome(), Outcome::Failure);
+
+        let query = LogQuery::new()
+            .with_outcome(Outcome::Success)
+            .with_since(start);
+        assert_eq!(manager.query(query).unwrap().count(), 1);
+        let query = LogQuery::new().with_until(start);
+        assert_eq!(manager.query(query).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_rotation_and_retention() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = LogsConfig::new()
+            .with_max_file_size(1)
+            .with_max_files(3)
+            .with_compress_rotated(true);
+        let mut manager = manager(temp_dir.path()).with_config(config);
+        manager.start_session().unwrap();
+        for i in 0..5 {
+            manager
+                .add_log(Log::new("get", Outcome::Success).with_key_id(format!("key-{}", i)))
+                .unwrap();
+        }
+
+        let files = manager.session_files().unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files[0].to_str().unwrap().ends_with(".zewos.zst"));
+        assert_eq!(manager.query(LogQuery::new()).unwrap().count(), 2);
+
+        let report = manager.verify_chain().unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.records, 2);
+
+        fs::remove_file(&files[0]).unwrap();
+        let error = manager.verify_chain().unwrap().first_error.unwrap();
+        assert_eq!(
+            error.kind,
+            ChainErrorKind::Missing {
+                expected_seq: 3,
+                found_seq: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_chain_detects_missing_session() {
+        let temp_dir = TempDir::new().unwrap();
+        session(temp_dir.path(), 1);
+        session(temp_dir.path(), 1);
+        let manager = session(temp_dir.path(), 1);
+        let files = manager.session_files().unwrap();
+        std::fs::remove_file(&files[1]).unwrap();
+
+        let error = manager.verify_chain().unwrap().first_error.unwrap();
+        assert_eq!(error.file, files[2]);
+        assert_eq!(
+            error.kind,
+            ChainErrorKind::Missing {
+                expected_seq: 1,
+                found_seq: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_chain_detects_truncation() {
+        let temp_dir = TempDir::new().unwrap();
+        session(temp_dir.path(), 2).end_session().unwrap();
+        let manager = session(temp_dir.path(), 1);
+        let files = manager.session_files().unwrap();
+        std::fs::remove_file(&files[1]).unwrap();
+
+        let error = manager.verify_chain().unwrap().first_error.unwrap();
+        assert_eq!(error.file, temp_dir.path().join(HEAD_FILE));
+        assert_eq!(
+            error.kind,
+            ChainErrorKind::Truncated {
+                expected_seq: 3,
+                found_seq: 2
+            }
+        );
+
+        std::fs::write(temp_dir.path().join(HEAD_FILE), "3|00|00\n").unwrap();
+        let error = manager.verify_chain().unwrap().first_error.unwrap();
+        assert_eq!(error.kind, ChainErrorKind::BadHead);
     }
 }
@@ -0,0 +1,88 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+use zewos_core::{derive::Deriver, signing::Keypair};
+
+const KEY_LEN: usize = 32;
+
+/// Random secret held by a store, kept in a file only its owner can read. Keys that must
+/// stay out of reach of other users of the machine, such as the audit log keys, are
+/// derived from it rather than from the machine fingerprint, which anyone can recompute.
+#[derive(Clone)]
+pub struct MasterKey {
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl MasterKey {
+    pub fn generate() -> Self {
+        let mut key = Zeroizing::new(vec![0u8; KEY_LEN]);
+        OsRng.fill_bytes(&mut key);
+        Self { key }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let key = Zeroizing::new(fs::read(path)?);
+        if key.len() != KEY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Malformed master key at {}", path.display()),
+            ));
+        }
+        Ok(Self { key })
+    }
+
+    /// Loads the key at `path`, generating it first if there is none yet.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(path) {
+            Ok(mut file) => {
+                let key = Self::generate();
+                file.write_all(&key.key)?;
+                file.sync_all()?;
+                Ok(key)
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Self::load(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 32 byte key for `info`.
+    pub fn derive(&self, info: &[u8]) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(Deriver::new(None, self.key.to_vec()).derive_key(info))
+    }
+
+    /// Signing keypair for `info`.
+    pub fn keypair(&self, info: &[u8]) -> io::Result<Keypair> {
+        Keypair::from_seed(&self.key, info).map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_or_create() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master.key");
+        let key = MasterKey::load_or_create(&path).unwrap();
+        let loaded = MasterKey::load_or_create(&path).unwrap();
+        assert_eq!(*key.derive(b"info"), *loaded.derive(b"info"));
+        assert_ne!(*key.derive(b"info"), *MasterKey::generate().derive(b"info"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::write(&path, b"short").unwrap();
+        assert!(MasterKey::load(&path).is_err());
+    }
+}
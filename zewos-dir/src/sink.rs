@@ -0,0 +1,215 @@
+use super::logs::{Log, LogsManager, Outcome};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// Destination for audit records. [`LogsManager`] is the default one; implement this to
+/// send records somewhere else instead.
+pub trait AuditSink: Send {
+    fn record(&mut self, log: Log) -> io::Result<()>;
+}
+
+impl AuditSink for LogsManager {
+    fn record(&mut self, log: Log) -> io::Result<()> {
+        self.add_log(log)
+    }
+}
+
+#[cfg(unix)]
+const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+/// `authpriv`, the facility meant for security and authorization messages.
+#[cfg(unix)]
+const FACILITY_AUTHPRIV: u8 = 10;
+#[cfg(unix)]
+const SEVERITY_WARNING: u8 = 4;
+#[cfg(unix)]
+const SEVERITY_INFO: u8 = 6;
+/// Structured data id, under the enterprise number reserved for documentation.
+#[cfg(unix)]
+const SD_ID: &str = "zewos@32473";
+
+/// Sends audit records to the local syslog daemon as RFC 5424 messages, falling back to
+/// the file log whenever the socket cannot be reached.
+#[cfg(unix)]
+pub struct SyslogSink {
+    socket_path: PathBuf,
+    socket: Option<UnixDatagram>,
+    fallback: Option<LogsManager>,
+    app_name: String,
+    hostname: String,
+    session_id: String,
+}
+
+#[cfg(unix)]
+impl SyslogSink {
+    pub fn new(fallback: LogsManager) -> Self {
+        Self {
+            fallback: Some(fallback),
+            ..Self::without_fallback()
+        }
+    }
+
+    /// A sink that fails to record while the socket cannot be reached, leaving it to the
+    /// caller to keep the record elsewhere.
+    pub fn without_fallback() -> Self {
+        Self {
+            socket_path: PathBuf::from(DEFAULT_SYSLOG_SOCKET),
+            socket: None,
+            fallback: None,
+            app_name: "zewos".to_string(),
+            hostname: whoami::fallible::hostname().unwrap_or_else(|_| "-".to_string()),
+            session_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    pub fn with_socket_path(mut self, socket_path: impl Into<PathBuf>) -> Self {
+        self.socket_path = socket_path.into();
+        self.socket = None;
+        self
+    }
+
+    pub fn with_app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = app_name.into();
+        self
+    }
+
+    pub fn format(&self, log: &Log) -> String {
+        let severity = match log.outcome() {
+            Outcome::Success => SEVERITY_INFO,
+            Outcome::Failure => SEVERITY_WARNING,
+        };
+        let mut data = format!(
+            "[{} operation=\"{}\" outcome=\"{}\"",
+            SD_ID,
+            escape(log.operation()),
+            log.outcome()
+        );
+        let optional = [
+            ("keyId", log.key_id()),
+            ("errorKind", log.error_kind().map(|kind| kind.as_str())),
+            ("errorId", log.error_id()),
+            (
+                "session",
+                Some(log.session_id()).filter(|id| !id.is_empty()),
+            ),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                data.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+            }
+        }
+        data.push(']');
+        format!(
+            "<{}>1 {} {} {} {} {} {} {}",
+            FACILITY_AUTHPRIV * 8 + severity,
+            log.timestamp()
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            self.hostname,
+            self.app_name,
+            log.pid(),
+            log.operation(),
+            data,
+            log.render()
+        )
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.socket.is_none() {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(&self.socket_path)?;
+            self.socket = Some(socket);
+        }
+        let result = self.socket.as_ref().unwrap().send(message).map(|_| ());
+        if result.is_err() {
+            // Reconnect on the next record, the daemon may have been restarted.
+            self.socket = None;
+        }
+        result
+    }
+}
+
+#[cfg(unix)]
+impl AuditSink for SyslogSink {
+    fn record(&mut self, log: Log) -> io::Result<()> {
+        let log = if log.session_id().is_empty() {
+            log.with_session_id(self.session_id.clone())
+        } else {
+            log
+        };
+        let message = self.format(&log);
+        match (self.send(message.as_bytes()), self.fallback.as_mut()) {
+            (Err(e), Some(fallback)) => fall_back(fallback, log, e),
+            (result, _) => result,
+        }
+    }
+}
+
+/// Writes `log`, which could not be sent because of `error`, to `logger` instead, failing
+/// with `error` if the logger has no session to write it to.
+pub fn fall_back(logger: &mut LogsManager, log: Log, error: io::Error) -> io::Result<()> {
+    if !logger.in_session() {
+        return Err(error);
+    }
+    logger.add_log(log)
+}
+
+/// Escapes the characters RFC 5424 reserves inside structured data parameter values.
+#[cfg(unix)]
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::logs::{ErrorKind, LogQuery};
+    use crate::master_key::MasterKey;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_syslog_sink_and_fallback() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("log.sock");
+        let server = UnixDatagram::bind(&socket_path).unwrap();
+
+        let key = MasterKey::generate();
+        let mut fallback = LogsManager::new(temp_dir.path().to_path_buf(), &key).unwrap();
+        fallback.start_session().unwrap();
+        let mut sink = SyslogSink::new(fallback.clone()).with_socket_path(&socket_path);
+
+        let log = Log::new("get", Outcome::Failure)
+            .with_key_id("hmac-\"]")
+            .with_error_kind(ErrorKind::KeyNotFound);
+        sink.record(log).unwrap();
+
+        let mut buffer = [0u8; 4096];
+        let len = server.recv(&mut buffer).unwrap();
+        let message = std::str::from_utf8(&buffer[..len]).unwrap();
+        assert!(message.starts_with("<84>1 "));
+        assert!(message.contains(" zewos "));
+        assert!(message.contains(
+            "[zewos@32473 operation=\"get\" outcome=\"failure\" keyId=\"hmac-\\\"\\]\" errorKind=\"key_not_found\" session=\""
+        ));
+
+        drop(server);
+        std::fs::remove_file(&socket_path).unwrap();
+        sink.record(Log::new("insert", Outcome::Success)).unwrap();
+
+        let logged: Vec<Log> = fallback
+            .query(LogQuery::new())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].operation(), "insert");
+    }
+}
@@ -1,24 +1,31 @@
 use dashmap::DashMap;
 
 use super::errors::BackupError;
-use super::hash::Sha256;
+use super::hash::{Sha256, ToHex};
 use super::{
-    compression::{compress_bytes, decompress_bytes},
-    object::Object,
+    compression::{compress_bytes, decompress_bytes, decompress_bytes_lossy},
+    merkle::{MerkleProof, MerkleTree},
+    object::{Object, OBJECT_FORMAT},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::Cursor;
 use std::time::SystemTime;
 pub use zewos_core::metadata::BackupMetadata;
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct BackupConfig {
     compression_level: Option<usize>,
+    /// Number of previous values kept for each key.
+    #[serde(default)]
+    history_limit: usize,
 }
 
 impl BackupConfig {
     pub fn new() -> Self {
         Self {
             compression_level: Some(3),
+            history_limit: 0,
         }
     }
 
@@ -26,6 +33,11 @@ impl BackupConfig {
         self.compression_level = Some(level);
         self
     }
+
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
 }
 impl Default for BackupConfig {
     fn default() -> Self {
@@ -33,38 +45,114 @@ impl Default for BackupConfig {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the entry already present in the store.
+    Skip,
+    /// Replace the existing entry with the imported one.
+    Overwrite,
+    /// Keep whichever entry was updated most recently.
+    KeepNewest,
+    /// Abort the whole merge without modifying the store.
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub inserted: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupIssue {
+    ObjectCount {
+        recorded: u64,
+        actual: u64,
+    },
+    TotalSize {
+        recorded: usize,
+        actual: usize,
+    },
+    ObjectSize {
+        key: Vec<u8>,
+        recorded: usize,
+        actual: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct PortableBackup {
+    data: Vec<u8>,
+    metadata: Vec<u8>,
+    config: Vec<u8>,
+}
+
 pub struct Backup {
     metadata: BackupMetadata,
     objects: Box<DashMap<Vec<u8>, Object>>,
-    hash: Sha256,
+    merkle: MerkleTree,
+    /// Keys of `objects` in order, rebuilt on load rather than stored.
+    keys: BTreeSet<Vec<u8>>,
+    /// Values each key held before, newest first. Kept for removed keys too.
+    history: History,
+    /// Merkle tree over the history of each key, so a write only rehashes its own key.
+    history_merkle: MerkleTree,
 
     config: BackupConfig,
 }
 
+type History = BTreeMap<Vec<u8>, VecDeque<Object>>;
+
+/// Contents of the object, metadata and config files.
+pub type Serialized = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+/// A decoded entry with the attributes hash it was written with.
+type Decoded = (Vec<u8>, Object, Sha256);
+
 impl Backup {
     pub fn new() -> Self {
         Self::with_config(BackupConfig::new())
     }
 
     pub fn with_config(config: BackupConfig) -> Self {
-        let metadata = BackupMetadata::new(0, config.compression_level);
+        let metadata = BackupMetadata {
+            object_format: OBJECT_FORMAT,
+            ..BackupMetadata::new(0, config.compression_level)
+        };
 
         Self {
             metadata,
             objects: Box::new(DashMap::new()),
-            hash: Sha256::new(&[]),
+            merkle: MerkleTree::new(),
+            keys: BTreeSet::new(),
+            history: History::new(),
+            history_merkle: MerkleTree::new(),
 
             config,
         }
     }
 
+    /// Inserts `v` under `k`, stamping it with the generation this write creates.
     pub fn insert(&mut self, k: Vec<u8>, v: Object) -> Result<Option<Object>, BackupError> {
-        let result = self.objects.insert(k, v.clone());
-        self.metadata.object_count += 1;
+        let v = v.with_version(self.metadata.generation + 1);
+        // Everything that can fail happens before anything changes.
+        let attributes_hash = v.attributes_hash()?;
+        let old = self.get(&k);
+        if let Some(old) = &old {
+            self.retain(k.clone(), old.clone())?;
+        }
+        self.merkle
+            .insert_hashed(k.clone(), &v.to_bytes(), attributes_hash);
+        self.keys.insert(k.clone());
+        match &old {
+            Some(old) => self.metadata.total_size -= old.len(),
+            None => self.metadata.object_count += 1,
+        }
         self.metadata.total_size += v.len();
-        self.metadata.last_modified = chrono::Utc::now();
-        self.update_hash()?;
-        Ok(result)
+        self.objects.insert(k, v);
+        self.commit();
+        Ok(old)
     }
 
     pub fn get(&self, k: &[u8]) -> Option<Object> {
@@ -76,19 +164,76 @@ impl Backup {
     }
 
     pub fn remove(&mut self, k: &[u8]) -> Result<Option<Object>, BackupError> {
-        let removed = self.objects.remove(k);
-        if let Some((_, obj)) = removed.clone() {
-            self.metadata.object_count -= 1;
-            self.metadata.total_size -= obj.len();
-            self.metadata.last_modified = chrono::Utc::now();
-            self.update_hash()?;
+        let Some(obj) = self.get(k) else {
+            return Ok(None);
+        };
+        // The removed value stays in the history, so the removal can be reverted. Kept
+        // first, as it is the only step that can fail.
+        self.retain(k.to_vec(), obj.clone())?;
+        self.objects.remove(k);
+        self.metadata.object_count -= 1;
+        self.metadata.total_size -= obj.len();
+        self.merkle.remove(k);
+        self.keys.remove(k);
+        self.commit();
+        Ok(Some(obj))
+    }
+
+    /// Keeps `old` as the most recent previous value of `k`, within the history limit.
+    fn retain(&mut self, k: Vec<u8>, old: Object) -> Result<(), BackupError> {
+        let limit = self.config.history_limit;
+        if limit == 0 || old.is_expired() {
+            return Ok(());
+        }
+        let mut revisions = self.history.get(&k).cloned().unwrap_or_default();
+        revisions.push_front(old);
+        revisions.truncate(limit);
+        hash_history(&mut self.history_merkle, k.clone(), &revisions)?;
+        self.history.insert(k, revisions);
+        Ok(())
+    }
+
+    /// Replaces the history of `k`, keeping at most the history limit of `revisions`.
+    pub(crate) fn set_history(
+        &mut self,
+        k: Vec<u8>,
+        mut revisions: VecDeque<Object>,
+    ) -> Result<(), BackupError> {
+        revisions.truncate(self.config.history_limit);
+        if revisions.is_empty() {
+            self.history.remove(&k);
+            self.history_merkle.remove(&k);
+        } else {
+            hash_history(&mut self.history_merkle, k.clone(), &revisions)?;
+            self.history.insert(k, revisions);
         }
-        Ok(removed.map(|(_, obj)| obj))
+        self.commit();
+        Ok(())
+    }
+
+    /// Previous values of `k`, newest first.
+    pub fn history(&self, k: &[u8]) -> Option<&VecDeque<Object>> {
+        self.history.get(k)
+    }
+
+    pub(crate) fn all_history(&self) -> &History {
+        &self.history
     }
     pub(crate) fn update(&mut self, backup: Backup) {
         self.metadata = backup.metadata;
         self.objects = backup.objects;
-        self.hash = backup.hash;
+        self.merkle = backup.merkle;
+        self.keys = backup.keys;
+        self.history = backup.history;
+        self.history_merkle = backup.history_merkle;
+    }
+
+    pub(crate) fn config(&self) -> BackupConfig {
+        self.config
+    }
+
+    pub fn keys(&self) -> &BTreeSet<Vec<u8>> {
+        &self.keys
     }
 
     pub fn serialize(&self) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), BackupError> {
@@ -98,19 +243,69 @@ impl Backup {
     }
 
     pub fn deserialize(metadata: &[u8], data: &[u8], config: &[u8]) -> Result<Self, BackupError> {
-        let metadata: BackupMetadata = serde_json::from_slice(metadata)?;
+        let mut metadata: BackupMetadata = serde_json::from_slice(metadata)?;
         let config: BackupConfig = serde_json::from_slice(config)?;
-        let decompressed = decompress_bytes(data)?;
-        let objects: Box<DashMap<Vec<u8>, Object>> = bincode::deserialize(&decompressed)?;
-        let mut backup = Self {
+        let format = metadata.object_format;
+        let (entries, history) = read_objects(data, format)?;
+        let objects = DashMap::new();
+        let mut merkle = MerkleTree::new();
+        // The tree as it was written, which only differs from `merkle` for stores from
+        // before the format was recorded.
+        let mut written = (format != OBJECT_FORMAT).then(MerkleTree::new);
+        for (key, object, attributes_hash) in entries {
+            if let Some(written) = written.as_mut() {
+                written.insert_hashed(key.clone(), &object.to_bytes(), attributes_hash);
+            }
+            merkle.insert(key.clone(), &object)?;
+            objects.insert(key, object);
+        }
+        let history_merkle = history_tree(&history)?;
+        let actual = history_hash(&history, &history_merkle);
+        if metadata.history_hash != actual {
+            return Err(BackupError::HashMismatch {
+                expected: metadata
+                    .history_hash
+                    .map(|h| h.encode_hex())
+                    .unwrap_or_default(),
+                actual: actual.map(|h| h.encode_hex()).unwrap_or_default(),
+            });
+        }
+        if let Some(expected) = metadata.merkle_root {
+            let actual = written.as_ref().unwrap_or(&merkle).root();
+            if expected != actual {
+                return Err(BackupError::HashMismatch {
+                    expected: expected.encode_hex(),
+                    actual: actual.encode_hex(),
+                });
+            }
+        }
+        if let (Some(expected), Some(actual)) = (metadata.state_hash, metadata.compute_state_hash())
+        {
+            if expected != actual {
+                return Err(BackupError::HashMismatch {
+                    expected: expected.encode_hex(),
+                    actual: actual.encode_hex(),
+                });
+            }
+        }
+        if written.is_some() {
+            // Verified as written, so it can be carried over to the current encoding.
+            metadata.object_format = OBJECT_FORMAT;
+            metadata.merkle_root = metadata.merkle_root.map(|_| merkle.root());
+            metadata.state_hash = metadata.compute_state_hash();
+            metadata.state_signature = None;
+        }
+        let keys = objects.iter().map(|entry| entry.key().clone()).collect();
+        Ok(Self {
             metadata,
-            objects,
-            hash: Sha256::new(&[]),
+            objects: Box::new(objects),
+            merkle,
+            keys,
+            history,
+            history_merkle,
 
             config,
-        };
-        backup.update_hash()?;
-        Ok(backup)
+        })
     }
     pub fn serialize_custom(
         &self,
@@ -118,21 +313,286 @@ impl Backup {
     ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), BackupError> {
         let metadata_json = serde_json::to_vec(&self.metadata)?;
         let config_json = serde_json::to_vec(&self.config)?;
-        let object_data = bincode::serialize(&self.objects)?;
+        let object_data = bincode::serialize(&(&self.objects, &self.history))?;
 
         let compressed = compress_bytes(&object_data, level.unwrap_or(3).try_into().unwrap())?;
 
         Ok((compressed, metadata_json, config_json))
     }
 
+    /// Like [`Backup::serialize`], with the state hash signed by `sign` in the metadata.
+    pub fn serialize_signed(
+        &self,
+        sign: impl FnOnce(&Sha256) -> Vec<u8>,
+    ) -> Result<Serialized, BackupError> {
+        let (data, _, config) = self.serialize()?;
+        let mut metadata = self.metadata.clone();
+        metadata.state_signature = metadata.state_hash.as_ref().map(sign);
+        Ok((data, serde_json::to_vec(&metadata)?, config))
+    }
+
+    pub fn export(&self) -> Result<Vec<u8>, BackupError> {
+        let (data, metadata, config) = self.serialize()?;
+        Ok(bincode::serialize(&PortableBackup {
+            data,
+            metadata,
+            config,
+        })?)
+    }
+
+    pub fn import(bytes: &[u8]) -> Result<Self, BackupError> {
+        let portable: PortableBackup = bincode::deserialize(bytes)?;
+        Self::deserialize(&portable.metadata, &portable.data, &portable.config)
+    }
+
+    pub fn merge(
+        &mut self,
+        other: Backup,
+        policy: ConflictPolicy,
+    ) -> Result<MergeReport, BackupError> {
+        if policy == ConflictPolicy::Abort {
+            if let Some(entry) = other
+                .objects
+                .iter()
+                .find(|entry| self.objects.contains_key(entry.key()))
+            {
+                return Err(BackupError::KeyConflict(hex::encode(entry.key())));
+            }
+        }
+
+        let mut report = MergeReport::default();
+        let mut history = other.history;
+        for (key, object) in other.objects.into_iter() {
+            let replace = match self.get(&key) {
+                None => {
+                    // A new key brings its previous values along.
+                    if let Some(revisions) = history.remove(&key) {
+                        self.set_history(key.clone(), revisions)?;
+                    }
+                    report.inserted += 1;
+                    true
+                }
+                Some(existing) => match policy {
+                    ConflictPolicy::Overwrite => true,
+                    ConflictPolicy::KeepNewest => {
+                        object.get_metadata().get_last_updated()
+                            > existing.get_metadata().get_last_updated()
+                    }
+                    ConflictPolicy::Skip | ConflictPolicy::Abort => false,
+                },
+            };
+            if !replace {
+                report.skipped += 1;
+                continue;
+            }
+            if self.insert(key, object)?.is_some() {
+                report.replaced += 1;
+            }
+        }
+        // Keys removed from `other` bring their history along where this store has none.
+        for (key, revisions) in history {
+            if !self.objects.contains_key(&key) && !self.history.contains_key(&key) {
+                self.set_history(key, revisions)?;
+            }
+        }
+        Ok(report)
+    }
+
     pub fn get_metadata(&self) -> Result<BackupMetadata, BackupError> {
         Ok(self.metadata.clone())
     }
 
-    fn update_hash(&mut self) -> Result<(), BackupError> {
-        self.hash = Sha256::new(&bincode::serialize(&self.objects)?);
-        Ok(())
+    /// Rebuilds a backup from whatever can still be read, dropping undecodable entries.
+    ///
+    /// Unreadable metadata or config fall back to defaults, and the object counters are
+    /// recomputed from the recovered objects.
+    pub fn recover(metadata: &[u8], data: &[u8], config: &[u8]) -> (Self, usize) {
+        let config: BackupConfig = serde_json::from_slice(config).unwrap_or_default();
+        let mut metadata: BackupMetadata =
+            serde_json::from_slice(metadata).unwrap_or_else(|_| BackupMetadata {
+                object_format: OBJECT_FORMAT,
+                ..BackupMetadata::new(0, config.compression_level)
+            });
+        let format = metadata.object_format;
+
+        let decompressed = decompress_bytes_lossy(data);
+        let mut cursor = Cursor::new(decompressed.as_slice());
+        let declared: u64 = bincode::deserialize_from(&mut cursor).unwrap_or(0);
+        let objects = DashMap::new();
+        let mut merkle = MerkleTree::new();
+        for _ in 0..declared {
+            let Ok((key, object, _)) = read_entry(&mut cursor, format) else {
+                break;
+            };
+            if merkle.insert(key.clone(), &object).is_ok() {
+                objects.insert(key, object);
+            }
+        }
+        let dropped = (declared as usize).saturating_sub(objects.len());
+        // History is only trusted when every object before it was read back.
+        let mut history: History = if dropped == 0 {
+            bincode::deserialize_from(&mut cursor).unwrap_or_default()
+        } else {
+            History::new()
+        };
+        let history_merkle = history_tree(&history).unwrap_or_else(|_| {
+            history.clear();
+            MerkleTree::new()
+        });
+
+        metadata.object_format = OBJECT_FORMAT;
+        metadata.object_count = objects.len() as u64;
+        metadata.total_size = objects.iter().map(|entry| entry.value().len()).sum();
+        let keys = objects.iter().map(|entry| entry.key().clone()).collect();
+        let mut backup = Self {
+            metadata,
+            objects: Box::new(objects),
+            merkle,
+            keys,
+            history,
+            history_merkle,
+
+            config,
+        };
+        backup.commit();
+        (backup, dropped)
+    }
+
+    /// Decodes each of the object, metadata and config files that is available on its own,
+    /// for checking a store whose other files are missing or unreadable. Returns the error
+    /// of each file that does not decode, in the same order.
+    pub fn check_files(
+        data: Option<&[u8]>,
+        metadata: Option<&[u8]>,
+        config: Option<&[u8]>,
+    ) -> [Option<BackupError>; 3] {
+        // Empty files belong to a store that was never saved.
+        let [data, metadata, config] =
+            [data, metadata, config].map(|file| file.filter(|file| !file.is_empty()));
+        let metadata = metadata.map(serde_json::from_slice::<BackupMetadata>);
+        let format = match &metadata {
+            Some(Ok(metadata)) => metadata.object_format,
+            _ => OBJECT_FORMAT,
+        };
+        let data = data.and_then(|data| read_objects(data, format).err());
+        let config = config.and_then(|config| serde_json::from_slice::<BackupConfig>(config).err());
+        [
+            data,
+            metadata.and_then(|metadata| metadata.err().map(BackupError::from)),
+            config.map(BackupError::from),
+        ]
+    }
+
+    /// Compares the recorded metadata with the stored objects.
+    pub fn check(&self) -> Vec<BackupIssue> {
+        let mut issues = Vec::new();
+        let actual_count = self.objects.len() as u64;
+        if self.metadata.object_count != actual_count {
+            issues.push(BackupIssue::ObjectCount {
+                recorded: self.metadata.object_count,
+                actual: actual_count,
+            });
+        }
+        let actual_size = self.objects.iter().map(|entry| entry.value().len()).sum();
+        if self.metadata.total_size != actual_size {
+            issues.push(BackupIssue::TotalSize {
+                recorded: self.metadata.total_size,
+                actual: actual_size,
+            });
+        }
+        for entry in self.objects.iter() {
+            if entry.value().size() != entry.value().len() {
+                issues.push(BackupIssue::ObjectSize {
+                    key: entry.key().clone(),
+                    recorded: entry.value().size(),
+                    actual: entry.value().len(),
+                });
+            }
+        }
+        issues
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.metadata.generation
+    }
+
+    /// Moves the generation forward to at least `min` without touching the objects.
+    pub fn advance_generation(&mut self, min: u64) {
+        if self.metadata.generation < min {
+            self.metadata.generation = min;
+            self.metadata.state_hash = self.metadata.compute_state_hash();
+            self.metadata.state_signature = None;
+        }
+    }
+
+    fn commit(&mut self) {
+        self.metadata.last_modified = chrono::Utc::now();
+        self.metadata.generation += 1;
+        self.metadata.merkle_root = Some(self.merkle.root());
+        self.metadata.history_hash = history_hash(&self.history, &self.history_merkle);
+        self.metadata.state_hash = self.metadata.compute_state_hash();
+        self.metadata.state_signature = None;
+    }
+
+    pub fn merkle_root(&self) -> Sha256 {
+        self.merkle.root()
+    }
+
+    pub fn prove(&self, k: &[u8]) -> Option<MerkleProof> {
+        self.merkle.prove(k)
+    }
+}
+
+/// Decodes the object file, written in `format`, into its entries and history.
+fn read_objects(data: &[u8], format: u32) -> Result<(Vec<Decoded>, History), BackupError> {
+    // Formats between the first and the current one were never released.
+    if format != 0 && format != OBJECT_FORMAT {
+        return Err(BackupError::UnsupportedFormat(format));
+    }
+    let decompressed = decompress_bytes(data)?;
+    let mut cursor = Cursor::new(decompressed.as_slice());
+    let count: u64 = bincode::deserialize_from(&mut cursor)?;
+    let entries = (0..count)
+        .map(|_| read_entry(&mut cursor, format))
+        .collect::<Result<_, _>>()?;
+    // Stores written before history was kept end after the objects.
+    let history = if (cursor.position() as usize) < decompressed.len() {
+        bincode::deserialize_from(&mut cursor)?
+    } else {
+        History::new()
+    };
+    Ok((entries, history))
+}
+
+/// Reads one `(key, object)` entry of the object map, with the attributes hash the object
+/// was written with.
+fn read_entry(cursor: &mut Cursor<&[u8]>, format: u32) -> Result<Decoded, BackupError> {
+    let key = bincode::deserialize_from(&mut *cursor)?;
+    let (object, attributes_hash) = Object::decode(&mut *cursor, format)?;
+    Ok((key, object, attributes_hash))
+}
+
+/// Adds the history of `k` to `tree`, replacing what it held before.
+fn hash_history(
+    tree: &mut MerkleTree,
+    k: Vec<u8>,
+    revisions: &VecDeque<Object>,
+) -> Result<(), BackupError> {
+    let bytes = bincode::serialize(revisions)?;
+    tree.insert_hashed(k, &bytes, Sha256::new(&[]));
+    Ok(())
+}
+
+fn history_tree(history: &History) -> Result<MerkleTree, BackupError> {
+    let mut tree = MerkleTree::new();
+    for (k, revisions) in history {
+        hash_history(&mut tree, k.clone(), revisions)?;
     }
+    Ok(tree)
+}
+
+fn history_hash(history: &History, tree: &MerkleTree) -> Option<Sha256> {
+    (!history.is_empty()).then(|| tree.root())
 }
 
 #[cfg(test)]
@@ -163,7 +623,7 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(backup.metadata.object_count, 1);
         assert_eq!(backup.metadata.total_size, 3);
-        assert_eq!(backup.get(&[0]), Some(obj));
+        assert_eq!(backup.get(&[0]), Some(obj.with_version(1)));
     }
 
     #[test]
@@ -173,7 +633,7 @@ mod tests {
         backup.insert(vec![0], obj.clone()).unwrap();
         let result = backup.remove(&[0]);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Some(obj));
+        assert_eq!(result.unwrap(), Some(obj.with_version(1)));
         assert_eq!(backup.metadata.object_count, 0);
         assert_eq!(backup.metadata.total_size, 0);
     }
@@ -222,4 +682,268 @@ mod tests {
         assert_eq!(metadata.object_count, 1);
         assert_eq!(metadata.total_size, 3);
     }
+
+    #[test]
+    fn test_backup_merkle_root_persisted() {
+        let mut backup = Backup::new();
+        backup
+            .insert(vec![0], Object::new(vec![1, 2, 3]).unwrap())
+            .unwrap();
+        backup
+            .insert(vec![1], Object::new(vec![4, 5, 6]).unwrap())
+            .unwrap();
+
+        let (data, metadata, config) = backup.serialize().unwrap();
+        let deserialized = Backup::deserialize(&metadata, &data, &config).unwrap();
+        assert_eq!(backup.metadata.merkle_root, Some(backup.merkle_root()));
+        assert_eq!(deserialized.merkle_root(), backup.merkle_root());
+
+        let proof = deserialized.prove(&[1]).unwrap();
+        assert!(proof.verify(&backup.merkle_root(), &[1], &[4, 5, 6]));
+    }
+
+    #[test]
+    fn test_backup_merkle_root_mismatch() {
+        let mut backup = Backup::new();
+        backup
+            .insert(vec![0], Object::new(vec![1, 2, 3]).unwrap())
+            .unwrap();
+        let (_, metadata, config) = backup.serialize().unwrap();
+
+        backup
+            .insert(vec![0], Object::new(vec![7, 8, 9]).unwrap())
+            .unwrap();
+        let (tampered, _, _) = backup.serialize().unwrap();
+
+        assert!(matches!(
+            Backup::deserialize(&metadata, &tampered, &config),
+            Err(BackupError::HashMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_backup_generation() {
+        let mut backup = Backup::new();
+        assert_eq!(backup.generation(), 0);
+        backup
+            .insert(vec![0], Object::new(vec![1, 2, 3]).unwrap())
+            .unwrap();
+        backup.remove(&[0]).unwrap();
+        backup.remove(&[0]).unwrap();
+        assert_eq!(backup.generation(), 2);
+
+        backup.advance_generation(10);
+        assert_eq!(backup.generation(), 10);
+        backup.advance_generation(5);
+        assert_eq!(backup.generation(), 10);
+
+        let (data, metadata, config) = backup.serialize().unwrap();
+        let deserialized = Backup::deserialize(&metadata, &data, &config).unwrap();
+        assert_eq!(deserialized.generation(), 10);
+
+        let mut forged: BackupMetadata = serde_json::from_slice(&metadata).unwrap();
+        forged.generation = 11;
+        let forged = serde_json::to_vec(&forged).unwrap();
+        assert!(matches!(
+            Backup::deserialize(&forged, &data, &config),
+            Err(BackupError::HashMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_backup_overwrite_keeps_counters() {
+        let mut backup = Backup::new();
+        backup
+            .insert(vec![0], Object::new(vec![1, 2, 3]).unwrap())
+            .unwrap();
+        backup
+            .insert(vec![0], Object::new(vec![4, 5]).unwrap())
+            .unwrap();
+        assert_eq!(backup.metadata.object_count, 1);
+        assert_eq!(backup.metadata.total_size, 2);
+        assert!(backup.check().is_empty());
+    }
+
+    #[test]
+    fn test_backup_check_and_recover() {
+        let mut backup = Backup::new();
+        // Large enough to span several zstd blocks, so a truncated tail keeps the head.
+        for i in 0..4 {
+            backup
+                .insert(vec![i], Object::new(vec![i; 100_000]).unwrap())
+                .unwrap();
+        }
+        backup.metadata.object_count = 7;
+        assert_eq!(
+            backup.check(),
+            vec![BackupIssue::ObjectCount {
+                recorded: 7,
+                actual: 4
+            }]
+        );
+
+        let (data, metadata, config) = backup.serialize_custom(Some(0)).unwrap();
+        let (recovered, dropped) = Backup::recover(&metadata, &data, &config);
+        assert_eq!(dropped, 0);
+        assert!(recovered.check().is_empty());
+
+        let truncated = &data[..data.len() - 40];
+        let (recovered, dropped) = Backup::recover(&metadata, truncated, b"garbage");
+        assert!(dropped > 0);
+        assert_eq!(recovered.get_objects().len() + dropped, 4);
+        assert!(recovered.check().is_empty());
+        let (data, metadata, config) = recovered.serialize().unwrap();
+        assert!(Backup::deserialize(&metadata, &data, &config).is_ok());
+    }
+
+    #[test]
+    fn test_backup_export_import() {
+        let mut backup = Backup::new();
+        backup
+            .insert(vec![0], Object::new(vec![1, 2, 3]).unwrap())
+            .unwrap();
+
+        let imported = Backup::import(&backup.export().unwrap()).unwrap();
+        assert_eq!(imported.get(&[0]).unwrap().to_bytes(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_backup_history() {
+        let mut backup = Backup::with_config(BackupConfig::new().with_history_limit(2));
+        for value in 1..=4 {
+            backup
+                .insert(vec![0], Object::new(vec![value]).unwrap())
+                .unwrap();
+        }
+        let previous: Vec<_> = backup
+            .history(&[0])
+            .unwrap()
+            .iter()
+            .map(Object::to_bytes)
+            .collect();
+        assert_eq!(previous, vec![vec![3], vec![2]]);
+
+        let (data, metadata, config) = backup.serialize().unwrap();
+        let deserialized = Backup::deserialize(&metadata, &data, &config).unwrap();
+        assert_eq!(deserialized.history(&[0]).unwrap().len(), 2);
+
+        backup.remove(&[0]).unwrap();
+        let previous: Vec<_> = backup
+            .history(&[0])
+            .unwrap()
+            .iter()
+            .map(Object::to_bytes)
+            .collect();
+        assert_eq!(previous, vec![vec![4], vec![3]]);
+        let (data, metadata, config) = backup.serialize().unwrap();
+        let deserialized = Backup::deserialize(&metadata, &data, &config).unwrap();
+        assert_eq!(deserialized.history(&[0]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_backup_merge_policies() {
+        let mut backup = Backup::new();
+        backup
+            .insert(vec![0], Object::new(vec![1]).unwrap())
+            .unwrap();
+
+        let incoming = || {
+            let mut other = Backup::new();
+            other
+                .insert(vec![0], Object::new(vec![2]).unwrap())
+                .unwrap();
+            other
+                .insert(vec![1], Object::new(vec![3]).unwrap())
+                .unwrap();
+            other
+        };
+
+        let report = backup.merge(incoming(), ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.inserted, 1);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(backup.get(&[0]).unwrap().to_bytes(), vec![1]);
+
+        assert!(matches!(
+            backup.merge(incoming(), ConflictPolicy::Abort),
+            Err(BackupError::KeyConflict(_))
+        ));
+
+        let report = backup.merge(incoming(), ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(report.replaced, 2);
+        assert_eq!(backup.get(&[0]).unwrap().to_bytes(), vec![2]);
+    }
+
+    /// Metadata and object files holding `b"key" => b"value"` with `attributes` encoded the
+    /// way `format` wrote them, `None` for stores from before the format was recorded.
+    fn legacy_store(
+        attributes: impl Serialize,
+        format: Option<u32>,
+        root: Option<Sha256>,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let mut written = MerkleTree::new();
+        let attributes_hash = Sha256::new(&bincode::serialize(&attributes).unwrap());
+        written.insert_hashed(b"key".to_vec(), b"value", attributes_hash);
+        let objects = BTreeMap::from([(b"key".to_vec(), (b"value".to_vec(), attributes))]);
+        let data = compress_bytes(&bincode::serialize(&objects).unwrap(), 3).unwrap();
+
+        let mut metadata = BackupMetadata::new(5, Some(3));
+        metadata.object_count = 1;
+        metadata.generation = 1;
+        metadata.merkle_root = Some(root.unwrap_or(written.root()));
+        metadata.state_hash = metadata.compute_state_hash();
+        let mut json = serde_json::to_value(&metadata).unwrap();
+        match format {
+            Some(format) => json["object_format"] = format.into(),
+            None => {
+                json.as_object_mut().unwrap().remove("object_format");
+            }
+        }
+        (serde_json::to_vec(&json).unwrap(), data)
+    }
+
+    #[test]
+    fn test_backup_loads_format_0() {
+        // Objects as written before entries carried a version.
+        #[derive(Serialize)]
+        struct MetadataV0 {
+            name: String,
+            size: usize,
+            created_at: i64,
+            last_updated: i64,
+        }
+        let attributes = || MetadataV0 {
+            name: "Object_1700000000".to_string(),
+            size: 5,
+            created_at: 1_700_000_000_000_000,
+            last_updated: 1_700_000_000_000_000,
+        };
+        let config = serde_json::to_vec(&BackupConfig::new()).unwrap();
+        let (metadata, data) = legacy_store(attributes(), None, None);
+
+        let backup = Backup::deserialize(&metadata, &data, &config).unwrap();
+        let object = backup.get(b"key").unwrap();
+        assert_eq!(object.to_bytes(), b"value");
+        assert_eq!(object.get_metadata().get_version(), 0);
+        assert_eq!(backup.metadata.object_format, OBJECT_FORMAT);
+        assert_eq!(backup.metadata.merkle_root, Some(backup.merkle_root()));
+        assert_eq!(backup.generation(), 1);
+
+        let (data, metadata, config) = backup.serialize().unwrap();
+        let reloaded = Backup::deserialize(&metadata, &data, &config).unwrap();
+        assert_eq!(reloaded.get(b"key"), Some(object));
+
+        let (metadata, data) = legacy_store(attributes(), None, Some(Sha256::new(b"other")));
+        assert!(matches!(
+            Backup::deserialize(&metadata, &data, &config),
+            Err(BackupError::HashMismatch { .. })
+        ));
+
+        for format in [1, 2, OBJECT_FORMAT + 1] {
+            let (metadata, data) = legacy_store((), Some(format), None);
+            assert!(matches!(
+                Backup::deserialize(&metadata, &data, &config),
+                Err(BackupError::UnsupportedFormat(_))
+            ));
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use std::io::{Error, Write};
+use std::io::{Error, Read, Write};
 use zstd::{decode_all, encode_all};
 
 pub fn compress_bytes(input: &[u8], level: i32) -> Result<Vec<u8>, Error> {
@@ -9,6 +9,22 @@ pub fn decompress_bytes(input: &[u8]) -> Result<Vec<u8>, Error> {
     decode_all(input)
 }
 
+/// Decompresses as much of `input` as possible, returning the readable prefix.
+pub fn decompress_bytes_lossy(input: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    let Ok(mut decoder) = zstd::Decoder::new(input) else {
+        return decompressed;
+    };
+    let mut buf = [0u8; 4096];
+    while let Ok(read) = decoder.read(&mut buf) {
+        if read == 0 {
+            break;
+        }
+        decompressed.extend_from_slice(&buf[..read]);
+    }
+    decompressed
+}
+
 pub fn compress_bytes_with_dict(
     input: &[u8],
     level: i32,
@@ -1,4 +1,5 @@
 use thiserror::Error;
+use zewos_core::logging::ErrorKind;
 #[derive(Error, Debug)]
 pub enum CacheError {
     #[error("Failed to insert fragment: {0}")]
@@ -25,6 +26,26 @@ pub enum StorageError {
     ObjectError(#[from] ObjectError),
     #[error("Cache error: {0}")]
     CacheError(#[from] CacheError),
+    #[error("Rollback detected: store is at generation {found}, expected at least {expected}")]
+    Rollback { found: u64, expected: u64 },
+    #[error("Rollback suspected: store is at generation {found} but has no generation mark")]
+    MissingGenerationMark { found: u64 },
+    #[error("Store is locked by another process")]
+    Busy,
+    #[error("Store is opened read-only")]
+    ReadOnly,
+    #[error("Key is reserved for internal use")]
+    ReservedKey,
+    #[error("Invalid namespace: {0}")]
+    InvalidNamespace(String),
+    #[error("Namespace {namespace} is over its quota of {limit}")]
+    QuotaExceeded { namespace: String, limit: String },
+    #[error("Entry has expired")]
+    Expired,
+    #[error("Version conflict: entry is at version {current:?}")]
+    Conflict { current: Option<u64> },
+    #[error("Store state is not signed with the store's key")]
+    StateSignature,
 }
 
 #[derive(Error, Debug)]
@@ -39,6 +60,12 @@ pub enum BackupError {
     ObjectError(#[from] ObjectError),
     #[error("No versions found")]
     NoVersionsFound,
+    #[error("Key already exists: {0}")]
+    KeyConflict(String),
+    #[error("Content hash mismatch: expected {expected}, found {actual}")]
+    HashMismatch { expected: String, actual: String },
+    #[error("Unsupported object format: {0}")]
+    UnsupportedFormat(u32),
 }
 
 #[derive(Debug, Error)]
@@ -52,3 +79,62 @@ pub enum ObjectError {
     #[error("Serialization error: {0}")]
     SerializeError(#[from] bincode::Error),
 }
+
+impl StorageError {
+    /// Broad category of the error, for audit records.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            StorageError::Io(e) => io_kind(e),
+            StorageError::Compression(_) | StorageError::Decompression(_) => ErrorKind::Corruption,
+            StorageError::KeyNotFound | StorageError::VersionNotFound | StorageError::Expired => {
+                ErrorKind::KeyNotFound
+            }
+            StorageError::BackupError(e) => e.kind(),
+            StorageError::ObjectError(_) | StorageError::StateSignature => ErrorKind::Corruption,
+            StorageError::CacheError(_) => ErrorKind::Other,
+            StorageError::Rollback { .. } | StorageError::MissingGenerationMark { .. } => {
+                ErrorKind::Rollback
+            }
+            StorageError::Busy => ErrorKind::Busy,
+            StorageError::ReadOnly => ErrorKind::Permission,
+            StorageError::ReservedKey | StorageError::InvalidNamespace(_) => ErrorKind::Other,
+            StorageError::QuotaExceeded { .. } => ErrorKind::Quota,
+            StorageError::Conflict { .. } => ErrorKind::Conflict,
+        }
+    }
+}
+
+impl BackupError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            BackupError::IoError(e) => io_kind(e),
+            BackupError::SerializationError(_) | BackupError::UnsupportedFormat(_) => {
+                ErrorKind::Other
+            }
+            BackupError::DeserializationError(_)
+            | BackupError::ObjectError(_)
+            | BackupError::HashMismatch { .. } => ErrorKind::Corruption,
+            BackupError::NoVersionsFound => ErrorKind::KeyNotFound,
+            BackupError::KeyConflict(_) => ErrorKind::Conflict,
+        }
+    }
+}
+
+fn io_kind(error: &std::io::Error) -> ErrorKind {
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied => ErrorKind::Permission,
+        // Encrypted files that fail authentication are reported as invalid data.
+        std::io::ErrorKind::InvalidData => ErrorKind::Decrypt,
+        _ => ErrorKind::Io,
+    }
+}
+
+impl StorageError {
+    /// Converts a failure to take a store lock, reporting contention as [`StorageError::Busy`].
+    pub fn from_lock(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::WouldBlock => StorageError::Busy,
+            _ => StorageError::Io(error),
+        }
+    }
+}
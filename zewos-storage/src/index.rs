@@ -1,14 +1,25 @@
-use super::errors::StorageError;
+use super::errors::{BackupError, StorageError};
+use super::hash::Sha256;
 use super::{
-    backup::{Backup, BackupConfig, BackupMetadata},
+    backup::{
+        Backup, BackupConfig, BackupIssue, BackupMetadata, ConflictPolicy, MergeReport, Serialized,
+    },
     cache::{CacheConfig, CacheManager},
-    object::Object,
+    merkle::MerkleProof,
+    object::{Metadata as ObjectMetadata, Object},
+    scan::{KeyScan, Page},
+    snapshot::{revision, Snapshot},
 };
-use std::sync::{Arc, RwLock};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::time::Duration;
 
 pub struct StorageIndex {
     backup: Arc<RwLock<Backup>>,
     cache: Arc<RwLock<CacheManager>>,
+    /// Snapshot of the current state, dropped on every write and rebuilt on demand.
+    snapshot: Arc<RwLock<Option<Snapshot>>>,
 }
 
 impl StorageIndex {
@@ -16,27 +27,209 @@ impl StorageIndex {
         cache_config: CacheConfig,
         backup_config: BackupConfig,
     ) -> Result<Self, StorageError> {
-        let backup = Arc::new(RwLock::new(Backup::with_config(backup_config)));
-        let cache = Arc::new(RwLock::new(CacheManager::new(cache_config)));
+        let backup = Backup::with_config(backup_config);
+        let cache = CacheManager::new(cache_config);
+
+        Ok(Self::from_parts(backup, cache))
+    }
+
+    fn from_parts(backup: Backup, cache: CacheManager) -> Self {
+        Self {
+            backup: Arc::new(RwLock::new(backup)),
+            cache: Arc::new(RwLock::new(cache)),
+            snapshot: Arc::new(RwLock::new(None)),
+        }
+    }
 
-        Ok(Self { backup, cache })
+    /// Write access to the backup. Any cached snapshot is dropped while the lock is held,
+    /// so a snapshot taken concurrently can never outlive the state it was built from.
+    fn backup_mut(&self) -> RwLockWriteGuard<'_, Backup> {
+        let backup = self.backup.write().unwrap();
+        *self.snapshot.write().unwrap() = None;
+        backup
+    }
+
+    /// Copies every live entry and its history, O(n) in the size of the store. The copy is
+    /// kept until the next write, so snapshots taken in between share it.
+    pub fn snapshot(&self) -> Snapshot {
+        let backup = self.backup.read().unwrap();
+        if let Some(snapshot) = self.snapshot.read().unwrap().as_ref() {
+            return snapshot.clone();
+        }
+        let objects: HashMap<_, _> = backup
+            .get_objects()
+            .iter()
+            .filter(|entry| !entry.value().is_expired())
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let history = backup
+            .all_history()
+            .iter()
+            .map(|(key, revisions)| (key.clone(), revisions.iter().cloned().collect()))
+            .collect();
+        let snapshot = Snapshot::new(objects, history, backup.generation(), backup.merkle_root());
+        *self.snapshot.write().unwrap() = Some(snapshot.clone());
+        snapshot
     }
 
     pub fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
-        let object = Object::new(value)?;
-        let result = self
-            .backup
-            .write()
-            .unwrap()
-            .insert(key.clone(), object.clone())?;
-        self.cache.write().unwrap().insert(key, object)?;
+        let (_, result) = self.insert_if(key, Object::new(value)?, |_| Ok(()))?;
+        Ok(result.map(|opt_obj| opt_obj.to_bytes()))
+    }
 
+    /// Inserts `value` so that it expires once `ttl` has passed.
+    pub fn insert_with_ttl(
+        &self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        // A ttl too large to represent simply never expires.
+        let expires_at = chrono::Duration::from_std(ttl)
+            .ok()
+            .and_then(|ttl| Utc::now().checked_add_signed(ttl));
+        let object = Object::new(value)?.with_expiry(expires_at);
+        let (_, result) = self.insert_if(key, object, |_| Ok(()))?;
         Ok(result.map(|opt_obj| opt_obj.to_bytes()))
     }
 
+    /// Writes `object` only if `condition` accepts the current entry, checked and applied
+    /// under a single write lock. Expired entries count as absent. Returns the new version
+    /// and the replaced object.
+    fn insert_if(
+        &self,
+        key: Vec<u8>,
+        object: Object,
+        condition: impl FnOnce(Option<&Object>) -> Result<(), StorageError>,
+    ) -> Result<(u64, Option<Object>), StorageError> {
+        let backup = self.backup_mut();
+        condition(
+            backup
+                .get(&key)
+                .filter(|object| !object.is_expired())
+                .as_ref(),
+        )?;
+        self.store(backup, key, object)
+    }
+
+    /// Inserts `object` through the held write lock and caches the stored copy.
+    fn store(
+        &self,
+        mut backup: RwLockWriteGuard<'_, Backup>,
+        key: Vec<u8>,
+        object: Object,
+    ) -> Result<(u64, Option<Object>), StorageError> {
+        let result = backup
+            .insert(key.clone(), object)?
+            .filter(|object| !object.is_expired());
+        let stored = backup.get(&key).ok_or(StorageError::KeyNotFound)?;
+        drop(backup);
+
+        let version = stored.get_metadata().get_version();
+        self.cache.write().unwrap().insert(key, stored)?;
+        Ok((version, result))
+    }
+
+    /// Replaces the value of `key` with `new` if it currently holds `expected`, where
+    /// `None` means the key must be absent. Returns the new version.
+    pub fn compare_and_swap(
+        &self,
+        key: Vec<u8>,
+        expected: Option<&[u8]>,
+        new: Vec<u8>,
+    ) -> Result<u64, StorageError> {
+        self.insert_if(key, Object::new(new)?, |current| {
+            if current.map(|object| object.to_bytes()).as_deref() == expected {
+                Ok(())
+            } else {
+                Err(conflict(current))
+            }
+        })
+        .map(|(version, _)| version)
+    }
+
+    pub fn insert_if_absent(&self, key: Vec<u8>, value: Vec<u8>) -> Result<u64, StorageError> {
+        self.insert_if(key, Object::new(value)?, |current| match current {
+            None => Ok(()),
+            Some(_) => Err(conflict(current)),
+        })
+        .map(|(version, _)| version)
+    }
+
+    /// Replaces the value of `key` if it is still at `expected_version`.
+    pub fn update(
+        &self,
+        key: Vec<u8>,
+        expected_version: u64,
+        value: Vec<u8>,
+    ) -> Result<u64, StorageError> {
+        self.insert_if(key, Object::new(value)?, |current| match current {
+            Some(object) if object.get_metadata().get_version() == expected_version => Ok(()),
+            _ => Err(conflict(current)),
+        })
+        .map(|(version, _)| version)
+    }
+
+    /// Value of `key` `n` writes ago, `0` being the current one.
+    pub fn get_version(&self, key: &[u8], n: usize) -> Result<Vec<u8>, StorageError> {
+        let backup = self.backup.read().unwrap();
+        let current = backup.get(key);
+        revision(
+            current.as_ref(),
+            backup.history(key).into_iter().flatten(),
+            n,
+        )
+        .map(Object::to_bytes)
+    }
+
+    /// Previous values of `key` with their metadata, newest first. A removed key keeps
+    /// its history, its last value first.
+    pub fn history(&self, key: &[u8]) -> Result<Vec<(Vec<u8>, ObjectMetadata)>, StorageError> {
+        let backup = self.backup.read().unwrap();
+        match backup.get(key) {
+            Some(object) => {
+                live(object)?;
+            }
+            None if backup.history(key).is_none() => return Err(StorageError::KeyNotFound),
+            None => {}
+        }
+        Ok(backup
+            .history(key)
+            .into_iter()
+            .flatten()
+            .filter(|object| !object.is_expired())
+            .map(|object| (object.to_bytes(), object.get_metadata().clone()))
+            .collect())
+    }
+
+    /// Writes the value `key` held `n` writes ago back as its current value, returning
+    /// the new version.
+    pub fn revert(&self, key: Vec<u8>, n: usize) -> Result<u64, StorageError> {
+        let backup = self.backup_mut();
+        let current = backup.get(&key);
+        let value = revision(
+            current.as_ref(),
+            backup.history(&key).into_iter().flatten(),
+            n,
+        )?
+        .to_bytes();
+        self.store(backup, key, Object::new(value)?)
+            .map(|(version, _)| version)
+    }
+
+    pub fn version(&self, key: &[u8]) -> Result<u64, StorageError> {
+        self.backup
+            .read()
+            .unwrap()
+            .get(key)
+            .ok_or(StorageError::KeyNotFound)
+            .and_then(live)
+            .map(|object| object.get_metadata().get_version())
+    }
+
     pub fn get(&self, key: &Vec<u8>) -> Result<Vec<u8>, StorageError> {
         if let Some(object) = self.cache.read().unwrap().get(key) {
-            return Ok(object.to_bytes());
+            return live(object).map(|object| object.to_bytes());
         }
 
         if let Some(object) = self.backup.read().unwrap().get(key) {
@@ -45,16 +238,55 @@ impl StorageIndex {
                 .write()
                 .unwrap()
                 .insert(key.clone(), object.clone());
-            return Ok(object.to_bytes());
+            return live(object).map(|object| object.to_bytes());
         }
 
         Err(StorageError::KeyNotFound)
     }
 
+    /// The value of `key` along with its metadata.
+    pub fn get_entry(&self, key: &[u8]) -> Result<(Vec<u8>, ObjectMetadata), StorageError> {
+        self.backup
+            .read()
+            .unwrap()
+            .get(key)
+            .ok_or(StorageError::KeyNotFound)
+            .and_then(live)
+            .map(|object| (object.to_bytes(), object.get_metadata().clone()))
+    }
+
     pub fn remove(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
-        let result = self.backup.write().unwrap().remove(key)?;
+        let result = self.backup_mut().remove(key)?;
         self.cache.write().unwrap().remove(key);
-        Ok(result.map(|obj| obj.to_bytes()))
+        Ok(result
+            .filter(|obj| !obj.is_expired())
+            .map(|obj| obj.to_bytes()))
+    }
+
+    pub fn has_expired(&self) -> bool {
+        self.backup
+            .read()
+            .unwrap()
+            .get_objects()
+            .iter()
+            .any(|entry| entry.value().is_expired())
+    }
+
+    /// Removes every expired entry, returning their keys.
+    pub fn purge_expired(&self) -> Result<Vec<Vec<u8>>, StorageError> {
+        let mut backup = self.backup_mut();
+        let expired: Vec<Vec<u8>> = backup
+            .get_objects()
+            .iter()
+            .filter(|entry| entry.value().is_expired())
+            .map(|entry| entry.key().clone())
+            .collect();
+        let cache = self.cache.write().unwrap();
+        for key in &expired {
+            backup.remove(key)?;
+            cache.remove(key);
+        }
+        Ok(expired)
     }
 
     pub fn serialize_backup(&self) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), StorageError> {
@@ -62,6 +294,14 @@ impl StorageIndex {
         let (data, metadata, config) = backup.serialize()?;
         Ok((data, metadata, config))
     }
+    /// Serializes the store with its state hash signed by `sign`.
+    pub fn serialize_backup_signed(
+        &self,
+        sign: impl FnOnce(&Sha256) -> Vec<u8>,
+    ) -> Result<Serialized, StorageError> {
+        Ok(self.backup.read().unwrap().serialize_signed(sign)?)
+    }
+
     pub fn serialize_backup_custom(
         &self,
         level: Option<usize>,
@@ -84,10 +324,39 @@ impl StorageIndex {
         };
         let cache = CacheManager::new(cache_config);
         cache.load_from_backup(&backup)?;
-        Ok(Self {
-            backup: Arc::new(RwLock::new(backup)),
-            cache: Arc::new(RwLock::new(cache)),
-        })
+        Ok(Self::from_parts(backup, cache))
+    }
+
+    pub fn check_backup(
+        data: &[u8],
+        metadata: &[u8],
+        config: &[u8],
+    ) -> Result<Vec<BackupIssue>, StorageError> {
+        if data.is_empty() && metadata.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(Backup::deserialize(metadata, data, config)?.check())
+    }
+
+    /// Checks each store file that could be read on its own, see [`Backup::check_files`].
+    pub fn check_files(
+        data: Option<&[u8]>,
+        metadata: Option<&[u8]>,
+        config: Option<&[u8]>,
+    ) -> [Option<StorageError>; 3] {
+        Backup::check_files(data, metadata, config).map(|error| error.map(StorageError::from))
+    }
+
+    pub fn recover_backup(
+        data: &[u8],
+        metadata: &[u8],
+        config: &[u8],
+        cache_config: CacheConfig,
+    ) -> Result<(StorageIndex, usize), StorageError> {
+        let (backup, dropped) = Backup::recover(metadata, data, config);
+        let cache = CacheManager::new(cache_config);
+        cache.load_from_backup(&backup)?;
+        Ok((Self::from_parts(backup, cache), dropped))
     }
 
     pub fn sync_cache(&self) -> Result<(), StorageError> {
@@ -108,10 +377,143 @@ impl StorageIndex {
         config: Vec<u8>,
     ) -> Result<(), StorageError> {
         let backup = Backup::deserialize(&metadata, &data, &config)?;
-        self.backup.write().unwrap().update(backup);
+        self.backup_mut().update(backup);
         Ok(())
     }
 
+    /// Replaces the contents with the given files, once `verify` accepts their metadata,
+    /// refusing a backup older than generation `min_generation`.
+    pub fn reload_backup(
+        &self,
+        data: Vec<u8>,
+        metadata: Vec<u8>,
+        config: Vec<u8>,
+        min_generation: u64,
+        verify: impl FnOnce(&BackupMetadata) -> Result<(), StorageError>,
+    ) -> Result<(), StorageError> {
+        let backup = if !data.is_empty() && !metadata.is_empty() {
+            Backup::deserialize(&metadata, &data, &config)?
+        } else {
+            Backup::new()
+        };
+        verify(&backup.get_metadata()?)?;
+        if backup.generation() < min_generation {
+            return Err(StorageError::Rollback {
+                found: backup.generation(),
+                expected: min_generation,
+            });
+        }
+        self.backup_mut().update(backup);
+        self.sync_cache()
+    }
+
+    /// Exports the entries whose key `keep` accepts, with their history, as a standalone
+    /// backup.
+    pub fn export_backup(&self, keep: impl Fn(&[u8]) -> bool) -> Result<Vec<u8>, StorageError> {
+        self.export_with(|key| keep(key).then_some(key), Ok)
+    }
+
+    /// Merges an exported backup into the store, once `accept` allows each of its keys.
+    pub fn import_backup(
+        &self,
+        bytes: &[u8],
+        policy: ConflictPolicy,
+        accept: impl Fn(&[u8]) -> Result<(), StorageError>,
+    ) -> Result<MergeReport, StorageError> {
+        let incoming = Backup::import(bytes)?;
+        for entry in incoming.get_objects().iter() {
+            accept(entry.key())?;
+        }
+        for key in incoming.all_history().keys() {
+            accept(key)?;
+        }
+        let report = self.backup_mut().merge(incoming, policy)?;
+        self.sync_cache()?;
+        Ok(report)
+    }
+
+    /// Every key under `prefix` in order, expired entries included.
+    pub fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        KeyScan::prefix(prefix)
+            .run(self.backup.read().unwrap().keys(), |_| true)
+            .keys
+    }
+
+    /// Number of entries under `prefix` and the total size of their values.
+    pub fn prefix_usage(&self, prefix: &[u8]) -> (usize, usize) {
+        let backup = self.backup.read().unwrap();
+        KeyScan::prefix(prefix)
+            .run(backup.keys(), |_| true)
+            .keys
+            .iter()
+            .fold((0, 0), |(count, size), key| {
+                let len = backup
+                    .get_objects()
+                    .get(key)
+                    .map_or(0, |object| object.len());
+                (count + 1, size + len)
+            })
+    }
+
+    /// Runs `scan` over the keys in order, leaving out expired entries and those `keep`
+    /// rejects.
+    pub fn scan(&self, scan: &KeyScan, keep: impl Fn(&[u8]) -> bool) -> Page {
+        let backup = self.backup.read().unwrap();
+        let objects = backup.get_objects();
+        scan.run(backup.keys(), |key| {
+            keep(key) && objects.get(key).is_some_and(|object| !object.is_expired())
+        })
+    }
+
+    /// Number of entries that have not expired and whose key `keep` accepts.
+    pub fn count(&self, keep: impl Fn(&[u8]) -> bool) -> usize {
+        self.backup
+            .read()
+            .unwrap()
+            .get_objects()
+            .iter()
+            .filter(|entry| keep(entry.key()) && !entry.value().is_expired())
+            .count()
+    }
+
+    /// Exports the entries under `prefix` as a standalone backup, with the prefix removed
+    /// from their keys and each value passed through `map`.
+    pub fn export_prefix(
+        &self,
+        prefix: &[u8],
+        map: impl Fn(Vec<u8>) -> Result<Vec<u8>, StorageError>,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.export_with(|key| key.strip_prefix(prefix), map)
+    }
+
+    /// Exports the entries `select` gives a key for, under that key, with each value passed
+    /// through `map`.
+    fn export_with(
+        &self,
+        select: impl Fn(&[u8]) -> Option<&[u8]>,
+        map: impl Fn(Vec<u8>) -> Result<Vec<u8>, StorageError>,
+    ) -> Result<Vec<u8>, StorageError> {
+        let backup = self.backup.read().unwrap();
+        let mut exported = Backup::with_config(backup.config());
+        for entry in backup.get_objects().iter() {
+            if let Some(key) = select(entry.key()) {
+                let object = entry.value().clone();
+                let data = map(object.to_bytes())?;
+                exported.insert(key.to_vec(), object.with_data(data))?;
+            }
+        }
+        for (key, revisions) in backup.all_history() {
+            if let Some(key) = select(key) {
+                let revisions = revisions
+                    .iter()
+                    .map(|object| Ok(object.clone().with_data(map(object.to_bytes())?)))
+                    .collect::<Result<_, StorageError>>()?;
+                exported.set_history(key.to_vec(), revisions)?;
+            }
+        }
+        Ok(exported.export()?)
+    }
+
     pub fn get_metadata(&self) -> Result<BackupMetadata, StorageError> {
         self.backup
             .read()
@@ -120,6 +522,36 @@ impl StorageIndex {
             .map_err(|e| StorageError::from(e))
     }
 
+    pub fn content_digest(&self) -> Sha256 {
+        self.backup.read().unwrap().merkle_root()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.backup.read().unwrap().generation()
+    }
+
+    /// Generation recorded in a serialized store's `metadata`, read without loading the
+    /// store. A store that has never been saved is at generation 0.
+    pub fn stored_generation(metadata: &[u8]) -> Result<u64, StorageError> {
+        if metadata.is_empty() {
+            return Ok(0);
+        }
+        let metadata: BackupMetadata =
+            serde_json::from_slice(metadata).map_err(BackupError::from)?;
+        Ok(metadata.generation)
+    }
+
+    pub fn advance_generation(&self, min: u64) {
+        self.backup_mut().advance_generation(min);
+    }
+
+    /// Proof that `key` is in the store, with the metadata of the state it was taken in.
+    pub fn prove(&self, key: &[u8]) -> Result<(MerkleProof, BackupMetadata), StorageError> {
+        let backup = self.backup.read().unwrap();
+        let proof = backup.prove(key).ok_or(StorageError::KeyNotFound)?;
+        Ok((proof, backup.get_metadata()?))
+    }
+
     pub fn clear_cache(&self) {
         self.cache.write().unwrap().clear();
     }
@@ -144,18 +576,17 @@ impl StorageIndex {
     }
 
     pub fn contains_key(&self, key: &Vec<u8>) -> Result<bool, StorageError> {
-        Ok(self.backup.read().unwrap().get(key).is_some())
-    }
-
-    pub fn get_all_keys(&self) -> Result<Vec<Vec<u8>>, StorageError> {
         Ok(self
             .backup
             .read()
             .unwrap()
-            .get_objects()
-            .iter()
-            .map(|entry| entry.key().to_vec())
-            .collect())
+            .get(key)
+            .is_some_and(|object| !object.is_expired()))
+    }
+
+    /// Keys of the entries that have not expired, in order.
+    pub fn get_all_keys(&self) -> Result<Vec<Vec<u8>>, StorageError> {
+        Ok(self.scan(&KeyScan::all(), |_| true).keys)
     }
 
     pub fn clear(&mut self) -> Result<(), StorageError> {
@@ -172,6 +603,20 @@ impl StorageIndex {
     }
 }
 
+fn live(object: Object) -> Result<Object, StorageError> {
+    if object.is_expired() {
+        Err(StorageError::Expired)
+    } else {
+        Ok(object)
+    }
+}
+
+fn conflict(current: Option<&Object>) -> StorageError {
+    StorageError::Conflict {
+        current: current.map(|object| object.get_metadata().get_version()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,6 +676,38 @@ mod tests {
         assert_eq!(loaded_index.get(&key2).unwrap(), value2);
     }
 
+    #[test]
+    fn test_snapshot() {
+        let index = StorageIndex::new(CacheConfig::default(), BackupConfig::default()).unwrap();
+        index.insert(b"key".to_vec(), b"old".to_vec()).unwrap();
+
+        let snapshot = index.snapshot();
+        assert_eq!(index.snapshot().generation(), snapshot.generation());
+
+        index.insert(b"key".to_vec(), b"new".to_vec()).unwrap();
+        assert_eq!(snapshot.get(b"key").unwrap(), b"old");
+        assert_eq!(index.snapshot().get(b"key").unwrap(), b"new");
+    }
+
+    #[test]
+    fn test_snapshot_hides_expired() {
+        let index = StorageIndex::new(CacheConfig::default(), BackupConfig::default()).unwrap();
+        index.insert(b"kept".to_vec(), vec![1]).unwrap();
+        index
+            .insert_with_ttl(b"key".to_vec(), vec![2], Duration::from_millis(50))
+            .unwrap();
+
+        let snapshot = index.snapshot();
+        assert!(snapshot.contains_key(b"key"));
+        std::thread::sleep(Duration::from_millis(100));
+
+        let snapshot = index.snapshot();
+        assert!(matches!(snapshot.get(b"key"), Err(StorageError::Expired)));
+        assert!(!snapshot.contains_key(b"key"));
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), [b"kept".as_slice()]);
+        assert_eq!(snapshot.len(), 1);
+    }
+
     #[test]
     fn test_clear_cache() {
         let index = StorageIndex::new(CacheConfig::default(), BackupConfig::default()).unwrap();
@@ -274,6 +751,23 @@ mod tests {
         assert_eq!(metadata.total_size, value.len());
     }
 
+    #[test]
+    fn test_content_digest() {
+        let index = StorageIndex::new(CacheConfig::default(), BackupConfig::default()).unwrap();
+        index.insert(b"key".to_vec(), b"value".to_vec()).unwrap();
+
+        let (data, metadata, config) = index.serialize_backup().unwrap();
+        let replica =
+            StorageIndex::deserialize_backup(data, metadata, config, CacheConfig::default())
+                .unwrap();
+        assert_eq!(index.content_digest(), replica.content_digest());
+
+        replica
+            .insert(b"other".to_vec(), b"value".to_vec())
+            .unwrap();
+        assert_ne!(index.content_digest(), replica.content_digest());
+    }
+
     #[test]
     fn test_evict_expired_cache() {
         let mut config = CacheConfig::default();
@@ -4,8 +4,15 @@ mod compression;
 pub mod errors;
 
 mod index;
+mod merkle;
 mod object;
-pub use backup::BackupConfig;
+mod scan;
+mod snapshot;
+pub use backup::{BackupConfig, BackupIssue, ConflictPolicy, MergeReport};
 pub use cache::CacheConfig;
 pub use index::*;
+pub use merkle::{MerkleProof, ProofStep, Side};
+pub use object::Metadata as ObjectMetadata;
+pub use scan::{Cursor, KeyScan, Page};
+pub use snapshot::Snapshot;
 use zewos_core::hash;
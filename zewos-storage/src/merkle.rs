@@ -0,0 +1,306 @@
+use super::errors::ObjectError;
+use super::hash::Sha256;
+use super::object::Object;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+const EMPTY: Sha256 = Sha256([0u8; 32]);
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+
+/// Merkle tree over `(key, object hash)` leaves.
+///
+/// The tree is a treap whose priorities are derived from the key hash, so its shape (and
+/// therefore its root) only depends on the stored content, while inserts and removals
+/// stay O(log n). Every node carries a leaf of its own besides its children hashes.
+#[derive(Default)]
+pub struct MerkleTree {
+    root: Option<Box<Node>>,
+}
+
+struct Node {
+    key: Vec<u8>,
+    priority: Sha256,
+    object_hash: Sha256,
+    attributes_hash: Sha256,
+    hash: Sha256,
+    left: Option<Box<Node>>,
+    right: Option<Box<Node>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub side: Side,
+    pub leaf: Sha256,
+    pub sibling: Sha256,
+}
+
+/// Proof that a key/value pair is part of the store state committed to by a Merkle root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub attributes_hash: Sha256,
+    pub left: Sha256,
+    pub right: Sha256,
+    pub path: Vec<ProofStep>,
+}
+
+pub fn object_hash(value: &[u8], attributes_hash: &Sha256) -> Sha256 {
+    Sha256::new(&[Sha256::new(value).0, attributes_hash.0].concat())
+}
+
+fn leaf_hash(key: &[u8], object_hash: &Sha256) -> Sha256 {
+    let mut data = Vec::with_capacity(1 + 8 + key.len() + 32);
+    data.push(LEAF_TAG);
+    data.extend_from_slice(&(key.len() as u64).to_be_bytes());
+    data.extend_from_slice(key);
+    data.extend_from_slice(&object_hash.0);
+    Sha256::new(&data)
+}
+
+fn node_hash(left: &Sha256, leaf: &Sha256, right: &Sha256) -> Sha256 {
+    Sha256::new(&[&[NODE_TAG][..], &left.0, &leaf.0, &right.0].concat())
+}
+
+fn hash_of(node: &Option<Box<Node>>) -> Sha256 {
+    node.as_ref().map_or(EMPTY, |n| n.hash)
+}
+
+fn priority_of(node: &Option<Box<Node>>) -> Option<Sha256> {
+    node.as_ref().map(|n| n.priority)
+}
+
+impl Node {
+    fn new(key: Vec<u8>, object_hash: Sha256, attributes_hash: Sha256) -> Self {
+        let mut node = Self {
+            priority: Sha256::new(&key),
+            key,
+            object_hash,
+            attributes_hash,
+            hash: EMPTY,
+            left: None,
+            right: None,
+        };
+        node.rehash();
+        node
+    }
+
+    fn leaf(&self) -> Sha256 {
+        leaf_hash(&self.key, &self.object_hash)
+    }
+
+    fn rehash(&mut self) {
+        self.hash = node_hash(&hash_of(&self.left), &self.leaf(), &hash_of(&self.right));
+    }
+}
+
+fn rotate_right(mut node: Box<Node>) -> Box<Node> {
+    let mut pivot = node.left.take().unwrap();
+    node.left = pivot.right.take();
+    node.rehash();
+    pivot.right = Some(node);
+    pivot.rehash();
+    pivot
+}
+
+fn rotate_left(mut node: Box<Node>) -> Box<Node> {
+    let mut pivot = node.right.take().unwrap();
+    node.right = pivot.left.take();
+    node.rehash();
+    pivot.left = Some(node);
+    pivot.rehash();
+    pivot
+}
+
+fn insert_node(node: Option<Box<Node>>, new: Node) -> Box<Node> {
+    let mut node = match node {
+        None => return Box::new(new),
+        Some(node) => node,
+    };
+    match new.key.cmp(&node.key) {
+        Ordering::Equal => {
+            node.object_hash = new.object_hash;
+            node.attributes_hash = new.attributes_hash;
+        }
+        Ordering::Less => {
+            node.left = Some(insert_node(node.left.take(), new));
+            if priority_of(&node.left) > Some(node.priority) {
+                return rotate_right(node);
+            }
+        }
+        Ordering::Greater => {
+            node.right = Some(insert_node(node.right.take(), new));
+            if priority_of(&node.right) > Some(node.priority) {
+                return rotate_left(node);
+            }
+        }
+    }
+    node.rehash();
+    node
+}
+
+fn merge(left: Option<Box<Node>>, right: Option<Box<Node>>) -> Option<Box<Node>> {
+    match (left, right) {
+        (None, node) | (node, None) => node,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.rehash();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.rehash();
+                Some(right)
+            }
+        }
+    }
+}
+
+fn remove_node(node: Option<Box<Node>>, key: &[u8], removed: &mut bool) -> Option<Box<Node>> {
+    let mut node = node?;
+    match key.cmp(&node.key) {
+        Ordering::Equal => {
+            *removed = true;
+            return merge(node.left.take(), node.right.take());
+        }
+        Ordering::Less => node.left = remove_node(node.left.take(), key, removed),
+        Ordering::Greater => node.right = remove_node(node.right.take(), key, removed),
+    }
+    node.rehash();
+    Some(node)
+}
+
+impl MerkleTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, object: &Object) -> Result<(), ObjectError> {
+        self.insert_hashed(key, &object.to_bytes(), object.attributes_hash()?);
+        Ok(())
+    }
+
+    /// Inserts `value` under `key` with attributes already hashed.
+    pub(crate) fn insert_hashed(&mut self, key: Vec<u8>, value: &[u8], attributes_hash: Sha256) {
+        let hash = object_hash(value, &attributes_hash);
+        self.root = Some(insert_node(
+            self.root.take(),
+            Node::new(key, hash, attributes_hash),
+        ));
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        let mut removed = false;
+        self.root = remove_node(self.root.take(), key, &mut removed);
+        removed
+    }
+
+    pub fn root(&self) -> Sha256 {
+        hash_of(&self.root)
+    }
+
+    pub fn prove(&self, key: &[u8]) -> Option<MerkleProof> {
+        let mut path = Vec::new();
+        let mut current = self.root.as_ref();
+        while let Some(node) = current {
+            let (side, next, sibling) = match key.cmp(&node.key) {
+                Ordering::Equal => {
+                    path.reverse();
+                    return Some(MerkleProof {
+                        attributes_hash: node.attributes_hash,
+                        left: hash_of(&node.left),
+                        right: hash_of(&node.right),
+                        path,
+                    });
+                }
+                Ordering::Less => (Side::Left, &node.left, &node.right),
+                Ordering::Greater => (Side::Right, &node.right, &node.left),
+            };
+            path.push(ProofStep {
+                side,
+                leaf: node.leaf(),
+                sibling: hash_of(sibling),
+            });
+            current = next.as_ref();
+        }
+        None
+    }
+}
+
+impl MerkleProof {
+    pub fn verify(&self, root: &Sha256, key: &[u8], value: &[u8]) -> bool {
+        let leaf = leaf_hash(key, &object_hash(value, &self.attributes_hash));
+        let mut hash = node_hash(&self.left, &leaf, &self.right);
+        for step in &self.path {
+            hash = match step.side {
+                Side::Left => node_hash(&hash, &step.leaf, &step.sibling),
+                Side::Right => node_hash(&step.sibling, &step.leaf, &hash),
+            };
+        }
+        &hash == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objects(count: u8) -> Vec<(Vec<u8>, Object)> {
+        (0..count)
+            .map(|i| (vec![i], Object::new(vec![i, i + 1]).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_root_is_order_independent() {
+        let entries = objects(32);
+        let mut forward = MerkleTree::new();
+        for (key, object) in entries.iter() {
+            forward.insert(key.clone(), object).unwrap();
+        }
+        let mut backward = MerkleTree::new();
+        for (key, object) in entries.iter().rev() {
+            backward.insert(key.clone(), object).unwrap();
+        }
+        assert_eq!(forward.root(), backward.root());
+    }
+
+    #[test]
+    fn test_remove_restores_root() {
+        let entries = objects(16);
+        let mut tree = MerkleTree::new();
+        for (key, object) in entries.iter().take(15) {
+            tree.insert(key.clone(), object).unwrap();
+        }
+        let before = tree.root();
+
+        let (key, object) = &entries[15];
+        tree.insert(key.clone(), object).unwrap();
+        assert_ne!(tree.root(), before);
+        assert!(tree.remove(key));
+        assert_eq!(tree.root(), before);
+        assert!(!tree.remove(key));
+    }
+
+    #[test]
+    fn test_inclusion_proof() {
+        let entries = objects(20);
+        let mut tree = MerkleTree::new();
+        for (key, object) in entries.iter() {
+            tree.insert(key.clone(), object).unwrap();
+        }
+        let root = tree.root();
+
+        for (key, object) in entries.iter() {
+            let proof = tree.prove(key).unwrap();
+            assert!(proof.verify(&root, key, &object.to_bytes()));
+            assert!(!proof.verify(&root, key, b"forged"));
+        }
+        assert!(tree.prove(b"missing").is_none());
+    }
+}
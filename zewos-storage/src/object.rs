@@ -1,6 +1,13 @@
 use super::errors::ObjectError;
+use super::hash::Sha256;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+/// Encoding of objects written by this version. Bump it whenever [`Metadata`] or the way
+/// the object file is hashed changes, and keep reading the previous encoding in
+/// [`Object::decode`].
+pub const OBJECT_FORMAT: u32 = 3;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Metadata {
@@ -10,6 +17,10 @@ pub struct Metadata {
     created_at: DateTime<Utc>,
     #[serde(with = "chrono::serde::ts_microseconds")]
     last_updated: DateTime<Utc>,
+    /// Generation of the store that last wrote this entry.
+    version: u64,
+    #[serde(with = "chrono::serde::ts_microseconds_option")]
+    expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -18,6 +29,23 @@ pub struct Object {
     metadata: Metadata,
 }
 
+/// Format 0 metadata, from before entries carried a version.
+#[derive(Serialize, Deserialize)]
+struct MetadataV0 {
+    name: String,
+    size: usize,
+    #[serde(with = "chrono::serde::ts_microseconds")]
+    created_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_microseconds")]
+    last_updated: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct ObjectV0 {
+    data: Vec<u8>,
+    metadata: MetadataV0,
+}
+
 impl Metadata {
     pub fn new(name: String, size: usize) -> Result<Self, ObjectError> {
         if name.is_empty() {
@@ -32,6 +60,8 @@ impl Metadata {
             size,
             created_at: now,
             last_updated: now,
+            version: 0,
+            expires_at: None,
         })
     }
 
@@ -51,6 +81,19 @@ impl Metadata {
         &self.last_updated
     }
 
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn get_expires_at(&self) -> Option<&DateTime<Utc>> {
+        self.expires_at.as_ref()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
     pub fn update(&mut self) {
         self.last_updated = Utc::now();
     }
@@ -68,10 +111,62 @@ impl Object {
     pub fn get_metadata(&self) -> &Metadata {
         &self.metadata
     }
+
+    /// Hash of everything stored alongside the value, used for Merkle leaves.
+    pub fn attributes_hash(&self) -> Result<Sha256, ObjectError> {
+        Ok(Sha256::new(&bincode::serialize(&self.metadata)?))
+    }
+
+    /// Reads an object encoded in `format`, upgrading older encodings. Also returns the
+    /// attributes hash the object had in that encoding, which its Merkle leaf was built on.
+    pub(crate) fn decode(reader: impl Read, format: u32) -> Result<(Self, Sha256), ObjectError> {
+        match format {
+            0 => {
+                let ObjectV0 { data, metadata } = bincode::deserialize_from(reader)?;
+                let attributes_hash = Sha256::new(&bincode::serialize(&metadata)?);
+                let metadata = Metadata {
+                    name: metadata.name,
+                    size: metadata.size,
+                    created_at: metadata.created_at,
+                    last_updated: metadata.last_updated,
+                    version: 0,
+                    expires_at: None,
+                };
+                Ok((Object { data, metadata }, attributes_hash))
+            }
+            _ => {
+                let object: Object = bincode::deserialize_from(reader)?;
+                let attributes_hash = object.attributes_hash()?;
+                Ok((object, attributes_hash))
+            }
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.data.clone()
     }
 
+    /// The same object holding `data` instead, keeping its name and timestamps.
+    pub(crate) fn with_data(mut self, data: Vec<u8>) -> Self {
+        self.metadata.size = data.len();
+        self.data = data;
+        self
+    }
+
+    pub(crate) fn with_version(mut self, version: u64) -> Self {
+        self.metadata.version = version;
+        self
+    }
+
+    pub(crate) fn with_expiry(mut self, expires_at: Option<DateTime<Utc>>) -> Self {
+        self.metadata.expires_at = expires_at;
+        self
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.metadata.is_expired()
+    }
+
     pub fn update_name(&mut self, name: String) -> Result<(), ObjectError> {
         if name.is_empty() {
             return Err(ObjectError::InvalidName("Name cannot be empty".to_string()));
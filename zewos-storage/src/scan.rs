@@ -0,0 +1,268 @@
+use std::collections::BTreeSet;
+use std::ops::{Bound, RangeBounds};
+
+/// Position to resume a [`KeyScan`] from, as returned in [`Page::next`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor(Vec<u8>);
+
+impl Cursor {
+    /// Restores a cursor from [`Cursor::as_bytes`], e.g. after handing it to a client.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Ordered scan over keys: everything, a prefix or a range, in either direction, in pages.
+#[derive(Debug, Clone)]
+pub struct KeyScan {
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    reverse: bool,
+    limit: Option<usize>,
+    after: Option<Cursor>,
+}
+
+impl Default for KeyScan {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl KeyScan {
+    pub fn all() -> Self {
+        Self {
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            reverse: false,
+            limit: None,
+            after: None,
+        }
+    }
+
+    pub fn prefix(prefix: &[u8]) -> Self {
+        let end = match prefix_end(prefix) {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        Self {
+            start: Bound::Included(prefix.to_vec()),
+            end,
+            ..Self::all()
+        }
+    }
+
+    pub fn range(range: impl RangeBounds<Vec<u8>>) -> Self {
+        Self {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            ..Self::all()
+        }
+    }
+
+    /// Walk the keys from last to first.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    /// Return at most `limit` keys, with a cursor for the rest.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Continue after the page that returned `cursor`.
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
+    /// The same scan over the keys under `prefix`, with its bounds and cursor taken as
+    /// relative to it.
+    pub fn under(self, prefix: &[u8]) -> Self {
+        let within = |key: Vec<u8>| [prefix, &key].concat();
+        let start = match self.start {
+            Bound::Unbounded => Bound::Included(prefix.to_vec()),
+            bound => bound.map(within),
+        };
+        let end = match self.end {
+            Bound::Unbounded => match prefix_end(prefix) {
+                Some(end) => Bound::Excluded(end),
+                None => Bound::Unbounded,
+            },
+            bound => bound.map(within),
+        };
+        Self {
+            start,
+            end,
+            after: self.after.map(|Cursor(key)| Cursor(within(key))),
+            ..self
+        }
+    }
+
+    pub(crate) fn run<'a>(
+        &self,
+        keys: &'a BTreeSet<Vec<u8>>,
+        keep: impl Fn(&[u8]) -> bool,
+    ) -> Page {
+        let (mut start, mut end) = (self.start.clone(), self.end.clone());
+        if let Some(Cursor(after)) = &self.after {
+            match self.reverse {
+                false => start = Bound::Excluded(after.clone()),
+                true => end = Bound::Excluded(after.clone()),
+            }
+        }
+        if is_empty(&start, &end) {
+            return Page::default();
+        }
+
+        let range = keys.range::<Vec<u8>, _>((start, end));
+        let matching: Box<dyn Iterator<Item = &'a Vec<u8>>> = match self.reverse {
+            false => Box::new(range),
+            true => Box::new(range.rev()),
+        };
+        let mut matching = matching.filter(|key| keep(key));
+        let keys: Vec<Vec<u8>> = match self.limit {
+            Some(limit) => matching.by_ref().take(limit).cloned().collect(),
+            None => matching.by_ref().cloned().collect(),
+        };
+        let next = match matching.next() {
+            Some(_) => keys.last().cloned().map(Cursor),
+            None => None,
+        };
+        Page { keys, next }
+    }
+}
+
+/// One page of a [`KeyScan`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Page {
+    pub keys: Vec<Vec<u8>>,
+    /// Where the next page starts, or `None` if this was the last one.
+    pub next: Option<Cursor>,
+}
+
+impl Page {
+    /// Removes `prefix` from every key and from the cursor, undoing [`KeyScan::under`].
+    pub fn strip_prefix(self, prefix: &[u8]) -> Self {
+        let strip = |key: Vec<u8>| key[prefix.len().min(key.len())..].to_vec();
+        Self {
+            keys: self.keys.into_iter().map(strip).collect(),
+            next: self.next.map(|Cursor(key)| Cursor(strip(key))),
+        }
+    }
+}
+
+/// Smallest key greater than every key starting with `prefix`, if there is one.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Whether the bounds exclude every key. `BTreeSet::range` panics on such bounds.
+fn is_empty(start: &Bound<Vec<u8>>, end: &Bound<Vec<u8>>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+        (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> BTreeSet<Vec<u8>> {
+        keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+    }
+
+    fn strings(page: &Page) -> Vec<&str> {
+        page.keys
+            .iter()
+            .map(|key| std::str::from_utf8(key).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_prefix_and_range() {
+        let set = keys(&["db/dev/a", "db/prod/a", "db/prod/b", "db/prod0", "web/a"]);
+        let all = |_: &[u8]| true;
+
+        let page = KeyScan::prefix(b"db/prod/").run(&set, all);
+        assert_eq!(strings(&page), ["db/prod/a", "db/prod/b"]);
+        assert!(page.next.is_none());
+
+        let page = KeyScan::range(b"db/prod/b".to_vec()..b"web/a".to_vec()).run(&set, all);
+        assert_eq!(strings(&page), ["db/prod/b", "db/prod0"]);
+
+        let page = KeyScan::prefix(b"db/").reverse().run(&set, all);
+        assert_eq!(
+            strings(&page),
+            ["db/prod0", "db/prod/b", "db/prod/a", "db/dev/a"]
+        );
+
+        let page = KeyScan::range(b"z".to_vec()..b"a".to_vec()).run(&set, all);
+        assert!(page.keys.is_empty());
+        assert_eq!(prefix_end(&[0x01, 0xff]), Some(vec![0x02]));
+        assert_eq!(prefix_end(&[0xff]), None);
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let set = keys(&["a", "b", "c", "d", "e"]);
+        for reverse in [false, true] {
+            let mut scan = KeyScan::all().with_limit(2);
+            if reverse {
+                scan = scan.reverse();
+            }
+            let mut seen = Vec::new();
+            loop {
+                let page = scan.run(&set, |key| key != b"c");
+                seen.extend(strings(&page).into_iter().map(String::from));
+                match page.next {
+                    Some(cursor) => scan = scan.with_cursor(cursor),
+                    None => break,
+                }
+            }
+            let mut expected = vec!["a", "b", "d", "e"];
+            if reverse {
+                expected.reverse();
+            }
+            assert_eq!(seen, expected);
+        }
+    }
+
+    #[test]
+    fn test_scan_under() {
+        let set = keys(&["ns/a", "ns/b", "ns/c", "other"]);
+        let page = KeyScan::range(b"b".to_vec()..)
+            .under(b"ns/")
+            .run(&set, |_| true)
+            .strip_prefix(b"ns/");
+        assert_eq!(strings(&page), ["b", "c"]);
+
+        let first = KeyScan::all()
+            .with_limit(1)
+            .under(b"ns/")
+            .run(&set, |_| true);
+        let cursor = first.strip_prefix(b"ns/").next.unwrap();
+        let page = KeyScan::all()
+            .with_cursor(cursor)
+            .under(b"ns/")
+            .run(&set, |_| true)
+            .strip_prefix(b"ns/");
+        assert_eq!(strings(&page), ["b", "c"]);
+    }
+}
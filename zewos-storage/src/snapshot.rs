@@ -0,0 +1,134 @@
+use super::errors::StorageError;
+use super::hash::Sha256;
+use super::object::{Metadata, Object};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Read-only view of the stored objects as they were at one generation of the store.
+/// Writes made after it was taken are not visible through it, and clones share the same
+/// data. Entries still stop being visible once they expire, like in the store itself.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    objects: Arc<HashMap<Vec<u8>, Object>>,
+    /// Previous values of the keys in `objects`, newest first.
+    history: Arc<HashMap<Vec<u8>, Vec<Object>>>,
+    generation: u64,
+    digest: Sha256,
+    /// Keys starting with this are left out.
+    hidden: Option<Arc<[u8]>>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(
+        objects: HashMap<Vec<u8>, Object>,
+        history: HashMap<Vec<u8>, Vec<Object>>,
+        generation: u64,
+        digest: Sha256,
+    ) -> Self {
+        Self {
+            objects: Arc::new(objects),
+            history: Arc::new(history),
+            generation,
+            digest,
+            hidden: None,
+        }
+    }
+
+    /// Leaves out the keys starting with `prefix`, as if they were not in the store.
+    pub fn hiding(mut self, prefix: &[u8]) -> Self {
+        self.hidden = Some(prefix.into());
+        self
+    }
+
+    fn is_hidden(&self, key: &[u8]) -> bool {
+        self.hidden
+            .as_deref()
+            .is_some_and(|prefix| key.starts_with(prefix))
+    }
+
+    fn object(&self, key: &[u8]) -> Option<&Object> {
+        self.objects.get(key).filter(|_| !self.is_hidden(key))
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.get_version(key, 0)
+    }
+
+    /// Value of `key` `n` writes ago, `0` being the current one.
+    pub fn get_version(&self, key: &[u8], n: usize) -> Result<Vec<u8>, StorageError> {
+        revision(
+            self.object(key),
+            self.history
+                .get(key)
+                .filter(|_| !self.is_hidden(key))
+                .into_iter()
+                .flatten(),
+            n,
+        )
+        .map(Object::to_bytes)
+    }
+
+    /// Previous values of `key` with their metadata, newest first.
+    pub fn history(&self, key: &[u8]) -> Vec<(Vec<u8>, Metadata)> {
+        self.history
+            .get(key)
+            .filter(|_| !self.is_hidden(key))
+            .into_iter()
+            .flatten()
+            .map(|object| (object.to_bytes(), object.get_metadata().clone()))
+            .collect()
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.object(key).is_some_and(|object| !object.is_expired())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.objects
+            .iter()
+            .filter(|(key, object)| !object.is_expired() && !self.is_hidden(key))
+            .map(|(key, _)| key.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys().next().is_none()
+    }
+
+    /// Generation of the store the snapshot was taken at.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Merkle root of the objects in the snapshot.
+    pub fn content_digest(&self) -> Sha256 {
+        self.digest
+    }
+}
+
+/// The object `n` writes back from `current`, skipping previous values that have expired.
+/// A removed key has no current object, but its previous values can still be reached.
+pub(crate) fn revision<'a>(
+    current: Option<&'a Object>,
+    previous: impl IntoIterator<Item = &'a Object>,
+    n: usize,
+) -> Result<&'a Object, StorageError> {
+    if n == 0 {
+        let current = current.ok_or(StorageError::KeyNotFound)?;
+        if current.is_expired() {
+            return Err(StorageError::Expired);
+        }
+        return Ok(current);
+    }
+    let mut previous = previous
+        .into_iter()
+        .filter(|object| !object.is_expired())
+        .peekable();
+    if current.is_none() && previous.peek().is_none() {
+        return Err(StorageError::KeyNotFound);
+    }
+    previous.nth(n - 1).ok_or(StorageError::VersionNotFound)
+}
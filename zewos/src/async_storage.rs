@@ -0,0 +1,193 @@
+use super::config::ZewosConfig;
+use super::storage::Storage;
+use std::future::Future;
+use tokio::task::{self, JoinHandle};
+use zewos_storage::errors::StorageError;
+
+/// Async front end to [`Storage`] for tokio runtimes. Compression, encryption and file I/O
+/// run on the blocking thread pool instead of the caller's task.
+///
+/// Writes are started as soon as the method is called: dropping the returned future only
+/// discards the result, the write itself still runs to completion. A write is therefore
+/// never left half applied by a cancelled task.
+#[derive(Clone)]
+pub struct AsyncStorage {
+    storage: Storage,
+}
+
+impl AsyncStorage {
+    pub async fn init(origin: impl Into<String>) -> Result<Self, StorageError> {
+        Self::init_with_config(origin, ZewosConfig::default()).await
+    }
+
+    pub async fn init_with_config(
+        origin: impl Into<String>,
+        config: ZewosConfig,
+    ) -> Result<Self, StorageError> {
+        let origin = origin.into();
+        join(spawn(move || Storage::init_with_config(&origin, config)))
+            .await
+            .map(Self::from)
+    }
+
+    /// See [`Storage::load`].
+    pub async fn load(
+        origin: impl Into<String>,
+        config: ZewosConfig,
+    ) -> Result<Self, StorageError> {
+        let origin = origin.into();
+        join(spawn(move || Storage::load(&origin, config)))
+            .await
+            .map(Self::from)
+    }
+
+    /// See [`Storage::adopt`].
+    pub async fn adopt(path: impl Into<String>, config: ZewosConfig) -> Result<Self, StorageError> {
+        let path = path.into();
+        join(spawn(move || Storage::adopt(&path, config)))
+            .await
+            .map(Self::from)
+    }
+
+    /// See [`Storage::open_read_only`].
+    pub async fn open_read_only(
+        path: impl Into<String>,
+        config: ZewosConfig,
+    ) -> Result<Self, StorageError> {
+        let path = path.into();
+        join(spawn(move || Storage::open_read_only(&path, config)))
+            .await
+            .map(Self::from)
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub async fn get(&self, key: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        let storage = self.storage.clone();
+        join(spawn(move || storage.get(&key))).await
+    }
+
+    pub fn insert(
+        &self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, StorageError>> + Send + 'static {
+        let storage = self.storage.clone();
+        join(spawn(move || storage.insert(key, value)))
+    }
+
+    pub fn remove(
+        &self,
+        key: Vec<u8>,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, StorageError>> + Send + 'static {
+        let storage = self.storage.clone();
+        join(spawn(move || storage.remove(&key)))
+    }
+
+    /// Writes the current state to disk.
+    pub fn flush(&self) -> impl Future<Output = Result<(), StorageError>> + Send + 'static {
+        let storage = self.storage.clone();
+        join(spawn(move || storage.save()))
+    }
+}
+
+impl From<Storage> for AsyncStorage {
+    fn from(storage: Storage) -> Self {
+        Self { storage }
+    }
+}
+
+type Outcome<T> = (Result<T, StorageError>, Option<String>);
+
+/// Runs `f` on the blocking thread pool, along with the [`Storage::last_error_id`] it leaves.
+fn spawn<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, StorageError> + Send + 'static,
+) -> JoinHandle<Outcome<T>> {
+    task::spawn_blocking(move || {
+        Storage::set_last_error_id(None);
+        let result = f();
+        (result, Storage::last_error_id())
+    })
+}
+
+/// Waits for `handle`, making the error id of a failure the caller's
+/// [`Storage::last_error_id`].
+async fn join<T>(handle: JoinHandle<Outcome<T>>) -> Result<T, StorageError> {
+    let (result, error_id) = match handle.await {
+        Ok(outcome) => outcome,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => return Err(std::io::Error::other(e.to_string()).into()),
+    };
+    if result.is_err() {
+        Storage::set_last_error_id(error_id);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuditSink, Log};
+    use std::sync::mpsc::{self, Sender};
+    use tempfile::TempDir;
+
+    /// Signals every insert recorded in the audit log.
+    struct Signal(Sender<()>);
+
+    impl AuditSink for Signal {
+        fn record(&mut self, log: Log) -> std::io::Result<()> {
+            if log.operation() == "insert" {
+                let _ = self.0.send(());
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = AsyncStorage::init(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+
+        storage.insert(b"key".to_vec(), vec![1]).await.unwrap();
+        assert_eq!(storage.get(b"key".to_vec()).await.unwrap(), vec![1]);
+        assert_eq!(
+            storage.remove(b"key".to_vec()).await.unwrap(),
+            Some(vec![1])
+        );
+        assert!(storage.get(b"key".to_vec()).await.is_err());
+        assert!(Storage::last_error_id().is_some());
+        storage.flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropped_write_completes() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = AsyncStorage::init(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+
+        let (inserted, on_insert) = mpsc::channel();
+        let storage =
+            AsyncStorage::from(storage.storage().clone().with_audit_sink(Signal(inserted)));
+
+        drop(storage.insert(b"key".to_vec(), vec![1]));
+        task::spawn_blocking(move || on_insert.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(storage.get(b"key".to_vec()).await.unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "blocking task panicked")]
+    async fn test_panic_is_resumed() {
+        let _ = join(spawn(|| -> Result<(), StorageError> {
+            panic!("blocking task panicked")
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,200 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use zewos_dir::logs::{KeyLabeler, Log, LogsManager};
+#[cfg(feature = "tracing")]
+use zewos_dir::logs::{KeyRedaction, Outcome};
+use zewos_dir::sink::{fall_back, AuditSink};
+
+/// Records that can be queued before callers have to wait for the writer to catch up.
+const QUEUE_LEN: usize = 1024;
+
+pub(crate) struct Audit {
+    pub(crate) logger: LogsManager,
+    pub(crate) sink: Option<Box<dyn AuditSink>>,
+    /// Whether records the sink fails to take are written to `logger` instead.
+    pub(crate) fallback: bool,
+}
+
+impl Audit {
+    fn write(&mut self, log: Log) -> io::Result<()> {
+        match self.sink.as_mut() {
+            Some(sink) => match sink.record(log.clone()) {
+                Err(e) if self.fallback => fall_back(&mut self.logger, log, e),
+                result => result,
+            },
+            None => self.logger.add_log(log),
+        }
+    }
+}
+
+enum Message {
+    /// A record, and where to report whether it was written, if anywhere.
+    Record(Log, Option<SyncSender<io::Result<()>>>),
+    /// Answered once every record queued before it has been written.
+    Wait(SyncSender<()>),
+}
+
+/// Writes audit records on a background thread, so that records of reads can be queued
+/// without waiting for the log to be written.
+pub(crate) struct AuditLog {
+    audit: Arc<Mutex<Audit>>,
+    labeler: KeyLabeler,
+    queue: Option<SyncSender<Message>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AuditLog {
+    pub(crate) fn spawn(logger: LogsManager) -> Self {
+        let labeler = logger.key_labeler();
+        let audit = Arc::new(Mutex::new(Audit {
+            logger,
+            sink: None,
+            fallback: false,
+        }));
+        let (queue, queued) = mpsc::sync_channel(QUEUE_LEN);
+        let writer = audit.clone();
+        let thread = thread::spawn(move || run(&writer, queued));
+        Self {
+            audit,
+            labeler,
+            queue: Some(queue),
+            thread: Some(thread),
+        }
+    }
+
+    pub(crate) fn key_label(&self, key: &[u8]) -> String {
+        self.labeler.label(key)
+    }
+
+    /// Span for `operation` on `key`, to enter for the length of the operation.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span(&self, operation: &str, key: Option<&[u8]>) -> tracing::Span {
+        let key_id = match self.labeler.key_redaction() {
+            KeyRedaction::Plain => None,
+            _ => key.map(|key| self.labeler.label(key)),
+        };
+        span(operation, key_id.as_deref())
+    }
+
+    /// Writes `log` and waits for it. Fails if it, or any record queued with
+    /// [`AuditLog::record_later`] since the last wait, could not be written.
+    pub(crate) fn record(&self, log: Log) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        trace(&log, self.labeler.key_redaction());
+        let (reply, replied) = mpsc::sync_channel(1);
+        self.send(Message::Record(log, Some(reply)))?;
+        replied.recv().map_err(|_| stopped())?
+    }
+
+    /// Queues `log` without waiting for it to be written.
+    pub(crate) fn record_later(&self, log: Log) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        trace(&log, self.labeler.key_redaction());
+        self.send(Message::Record(log, None))
+    }
+
+    /// The logger and sink, once every record queued so far has been written.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Audit> {
+        let (reply, replied) = mpsc::sync_channel(1);
+        if self.send(Message::Wait(reply)).is_ok() {
+            let _ = replied.recv();
+        }
+        self.audit.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn send(&self, message: Message) -> io::Result<()> {
+        match &self.queue {
+            Some(queue) => queue.send(message).map_err(|_| stopped()),
+            None => Err(stopped()),
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        drop(self.queue.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let mut audit = self.audit.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = audit.logger.end_session();
+    }
+}
+
+fn run(audit: &Mutex<Audit>, queued: Receiver<Message>) {
+    // The first queued record that could not be written, reported to the next caller
+    // that waits for its own record.
+    let mut failed = None;
+    for message in queued {
+        match message {
+            Message::Record(log, reply) => {
+                let result = audit
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .write(log);
+                match reply {
+                    Some(reply) => {
+                        let _ = reply.send(failed.take().map_or(result, Err));
+                    }
+                    None => {
+                        if let Err(e) = result {
+                            failed.get_or_insert(e);
+                        }
+                    }
+                }
+            }
+            Message::Wait(reply) => {
+                let _ = reply.send(());
+            }
+        }
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::other("Audit log writer has stopped")
+}
+
+pub(crate) fn write_log(logger: &mut LogsManager, log: Log) -> io::Result<()> {
+    #[cfg(feature = "tracing")]
+    trace(&log, logger.key_redaction());
+    logger.add_log(log)
+}
+
+/// Span wrapping a storage operation, so that its `tracing` event, and anything the caller
+/// records meanwhile, is attached to it. `key_id` must already be redacted.
+#[cfg(feature = "tracing")]
+pub(crate) fn span(operation: &str, key_id: Option<&str>) -> tracing::Span {
+    tracing::info_span!(target: "zewos", "zewos", operation, key_id)
+}
+
+/// Mirrors an audit record as a `tracing` event, emitted on the thread of the operation so
+/// it lands in the operation's span. Key ids are left out when keys are logged in plain
+/// text, so key names never reach the subscriber.
+#[cfg(feature = "tracing")]
+fn trace(log: &Log, key_redaction: KeyRedaction) {
+    let key_id = match key_redaction {
+        KeyRedaction::Plain => None,
+        _ => log.key_id(),
+    };
+    match log.outcome() {
+        Outcome::Success => tracing::info!(
+            target: "zewos",
+            operation = log.operation(),
+            key_id,
+            details = log.details(),
+            "zewos {}",
+            log.operation()
+        ),
+        Outcome::Failure => tracing::warn!(
+            target: "zewos",
+            operation = log.operation(),
+            key_id,
+            error_kind = log.error_kind().map(|kind| kind.as_str()),
+            error_id = log.error_id(),
+            "zewos {} failed",
+            log.operation()
+        ),
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+pub use zewos_storage::BackupIssue;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The file could not be read or decrypted.
+    Unreadable {
+        file: PathBuf,
+        reason: String,
+    },
+    /// The file mode or owner differs from what `PermissionsManager` sets up.
+    Permissions {
+        file: PathBuf,
+    },
+    /// The files decrypt but do not form a valid backup.
+    Corrupted {
+        reason: String,
+    },
+    /// The store is older than the last generation seen on this machine.
+    Rollback {
+        found: u64,
+        expected: u64,
+    },
+    /// The store has been written to, but the generation mark next to it is gone.
+    MissingGenerationMark {
+        found: u64,
+    },
+    Backup(BackupIssue),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub issues: Vec<Issue>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub permissions_fixed: usize,
+    pub recovered: usize,
+    pub dropped: usize,
+    /// Where the files were copied before being rewritten.
+    pub originals: PathBuf,
+}
@@ -1,9 +1,48 @@
+use std::time::Duration;
+pub use zewos_dir::logs::{KeyRedaction, LogsConfig};
 use zewos_storage::{BackupConfig, CacheConfig};
+
+/// What to do when a store older than the last one seen on this machine is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackPolicy {
+    Refuse,
+    Warn,
+    Ignore,
+}
+
+/// Which successful operations are written to the audit log. Failures and operations that
+/// change the store are always recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    /// Record every operation, reads included.
+    All,
+    /// Leave out successful reads.
+    Writes,
+}
+
+/// When to pick up changes another process has made to the store files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadPolicy {
+    /// Only on [`Storage::reload`](crate::Storage::reload) or from a
+    /// [`Watcher`](crate::Watcher).
+    Manual,
+    /// Check the files before every read. Only read-only handles check, since a writable
+    /// handle keeps other processes from changing the files.
+    OnRead,
+}
+
 #[derive(Clone, Copy)]
 pub struct ZewosConfig {
     pub logging: bool,
     pub backup_config: BackupConfig,
     pub cache_config: CacheConfig,
+    pub rollback_policy: RollbackPolicy,
+    pub key_redaction: KeyRedaction,
+    pub logs_config: LogsConfig,
+    pub log_level: LogLevel,
+    /// How long to wait for another process to release the store. `None` fails at once.
+    pub lock_timeout: Option<Duration>,
+    pub reload_policy: ReloadPolicy,
 }
 impl ZewosConfig {
     pub fn new() -> Self {
@@ -11,6 +50,12 @@ impl ZewosConfig {
             logging: true,
             backup_config: BackupConfig::default(),
             cache_config: CacheConfig::default(),
+            rollback_policy: RollbackPolicy::Refuse,
+            key_redaction: KeyRedaction::Hmac,
+            logs_config: LogsConfig::default(),
+            log_level: LogLevel::All,
+            lock_timeout: None,
+            reload_policy: ReloadPolicy::Manual,
         }
     }
     pub fn with_logging(mut self, logging: bool) -> Self {
@@ -25,6 +70,30 @@ impl ZewosConfig {
         self.cache_config = cache_config;
         self
     }
+    pub fn with_rollback_policy(mut self, rollback_policy: RollbackPolicy) -> Self {
+        self.rollback_policy = rollback_policy;
+        self
+    }
+    pub fn with_key_redaction(mut self, key_redaction: KeyRedaction) -> Self {
+        self.key_redaction = key_redaction;
+        self
+    }
+    pub fn with_logs_config(mut self, logs_config: LogsConfig) -> Self {
+        self.logs_config = logs_config;
+        self
+    }
+    pub fn with_log_level(mut self, log_level: LogLevel) -> Self {
+        self.log_level = log_level;
+        self
+    }
+    pub fn with_lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = Some(lock_timeout);
+        self
+    }
+    pub fn with_reload_policy(mut self, reload_policy: ReloadPolicy) -> Self {
+        self.reload_policy = reload_policy;
+        self
+    }
 }
 
 impl Default for ZewosConfig {
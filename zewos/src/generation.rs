@@ -0,0 +1,60 @@
+use std::io;
+use std::path::Path;
+use zewos_dir::file::File;
+
+/// Highest store generation seen on this machine.
+///
+/// Kept next to the `.zewos` folder rather than inside it, so restoring an old copy of
+/// the folder does not restore the mark along with it.
+pub(crate) struct GenerationMark {
+    file: Option<File>,
+}
+
+impl GenerationMark {
+    pub(crate) fn new(origin: &Path) -> Self {
+        Self {
+            file: Some(File::new(origin.join(".zewos-generation"))),
+        }
+    }
+
+    /// The mark as it is, without creating it if it does not exist yet. Such a mark cannot
+    /// be stored to.
+    pub(crate) fn existing(origin: &Path) -> Self {
+        let path = origin.join(".zewos-generation");
+        Self {
+            file: path.exists().then(|| File::new(path)),
+        }
+    }
+
+    /// The generation in the mark, or `None` if there is no mark yet.
+    pub(crate) fn load(&self) -> io::Result<Option<u64>> {
+        let Some(file) = &self.file else {
+            return Ok(None);
+        };
+        let contents = file.read()?;
+        if contents.is_empty() {
+            return Ok(None);
+        }
+        let bytes = contents
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid generation mark"))?;
+        Ok(Some(u64::from_be_bytes(bytes)))
+    }
+
+    pub(crate) fn store(&self, generation: u64) -> io::Result<()> {
+        if self.load()?.is_none_or(|mark| generation > mark) {
+            self.reset(generation)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reset(&self, generation: u64) -> io::Result<()> {
+        match &self.file {
+            Some(file) => file.write(&generation.to_be_bytes()),
+            None => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Generation mark is read-only",
+            )),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use super::namespace;
+use super::storage::Storage;
+use std::collections::VecDeque;
+use zewos_storage::{errors::StorageError, KeyScan, ObjectMetadata};
+
+/// Keys fetched from the store at a time.
+const BATCH_SIZE: usize = 64;
+
+/// An entry with the metadata stored alongside its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub metadata: ObjectMetadata,
+}
+
+/// Keys outside of namespaces in order, from [`Storage::keys`].
+///
+/// Keys are fetched a batch at a time, so the store is neither copied nor locked for the
+/// whole iteration. Writes made meanwhile may or may not be seen.
+pub struct KeyIter {
+    storage: Storage,
+    scan: Option<KeyScan>,
+    batch: VecDeque<Vec<u8>>,
+}
+
+impl KeyIter {
+    pub(crate) fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            scan: Some(KeyScan::all().with_limit(BATCH_SIZE)),
+            batch: VecDeque::new(),
+        }
+    }
+}
+
+impl Iterator for KeyIter {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty() {
+            let scan = self.scan.take()?;
+            let page = self
+                .storage
+                .index()
+                .scan(&scan, |key| namespace::user_key(key).is_ok());
+            self.scan = page.next.map(|cursor| scan.with_cursor(cursor));
+            self.batch = page.keys.into();
+        }
+        self.batch.pop_front()
+    }
+}
+
+/// Entries outside of namespaces in key order, from [`Storage::entries_with_metadata`].
+/// Fetched lazily like [`KeyIter`]; entries removed before they are reached are skipped.
+pub struct EntryIter {
+    keys: KeyIter,
+}
+
+impl EntryIter {
+    pub(crate) fn new(storage: Storage) -> Self {
+        Self {
+            keys: KeyIter::new(storage),
+        }
+    }
+}
+
+impl Iterator for EntryIter {
+    type Item = Result<Entry, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = self.keys.next()?;
+            match self.keys.storage.index().get_entry(&key) {
+                Ok((value, metadata)) => {
+                    return Some(Ok(Entry {
+                        key,
+                        value,
+                        metadata,
+                    }))
+                }
+                Err(StorageError::KeyNotFound | StorageError::Expired) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Entries outside of namespaces as `(key, value)` pairs in key order, from
+/// [`Storage::iter`]. Fetched lazily like [`EntryIter`].
+pub struct PairIter {
+    entries: EntryIter,
+}
+
+impl PairIter {
+    pub(crate) fn new(storage: Storage) -> Self {
+        Self {
+            entries: EntryIter::new(storage),
+        }
+    }
+}
+
+impl Iterator for PairIter {
+    type Item = Result<(Vec<u8>, Vec<u8>), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        Some(entry.map(|entry| (entry.key, entry.value)))
+    }
+}
+
+/// Values of the entries outside of namespaces in key order, from [`Storage::values`].
+/// Fetched lazily like [`EntryIter`].
+pub struct ValueIter {
+    entries: EntryIter,
+}
+
+impl ValueIter {
+    pub(crate) fn new(storage: Storage) -> Self {
+        Self {
+            entries: EntryIter::new(storage),
+        }
+    }
+}
+
+impl Iterator for ValueIter {
+    type Item = Result<Vec<u8>, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        Some(entry.map(|entry| entry.value))
+    }
+}
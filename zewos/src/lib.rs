@@ -1,4 +1,20 @@
+#[cfg(feature = "async")]
+mod async_storage;
+mod audit;
+mod check;
 mod config;
+mod generation;
+mod iter;
+mod namespace;
+mod proof;
 mod storage;
+mod watch;
+#[cfg(feature = "async")]
+pub use async_storage::*;
+pub use check::*;
 pub use config::*;
+pub use iter::{Entry, EntryIter, KeyIter, PairIter, ValueIter};
+pub use namespace::{Namespace, NamespaceConfig};
+pub use proof::{SignedProof, SignedRoot};
 pub use storage::*;
+pub use watch::Watcher;
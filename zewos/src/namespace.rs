@@ -0,0 +1,319 @@
+use super::storage::{KdfParams, Storage};
+use std::path::Path;
+use zewos_dir::archive::Archive;
+use zewos_dir::cipher::Cipher;
+use zewos_storage::{errors::StorageError, KeyScan, Page, StorageIndex};
+
+/// Every internal key starts with this, and keys outside a namespace may not.
+pub(crate) const RESERVED_PREFIX: &[u8] = b"\0ns";
+const ENTRY_PREFIX: &[u8] = b"\0ns\0";
+const CONFIG_PREFIX: &[u8] = b"\0nsconf\0";
+const CONFIG_VERSION: u8 = 1;
+
+/// Limits and encryption settings of a namespace, stored in the store itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NamespaceConfig {
+    pub max_entries: Option<usize>,
+    /// Limit on the stored size of all values in the namespace, in bytes.
+    pub max_bytes: Option<usize>,
+    /// Encrypt values with a key of their own, derived from the store's master key and the
+    /// namespace name.
+    pub encrypt: bool,
+}
+
+impl NamespaceConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+    pub fn with_encrypt(mut self, encrypt: bool) -> Self {
+        self.encrypt = encrypt;
+        self
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let limit = |limit: Option<usize>| limit.map_or(u64::MAX, |limit| limit as u64);
+        let mut bytes = vec![CONFIG_VERSION, self.encrypt as u8];
+        bytes.extend_from_slice(&limit(self.max_entries).to_be_bytes());
+        bytes.extend_from_slice(&limit(self.max_bytes).to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        let invalid = || StorageError::InvalidNamespace("unreadable namespace config".into());
+        let [CONFIG_VERSION, encrypt, rest @ ..] = bytes else {
+            return Err(invalid());
+        };
+        let (max_entries, max_bytes) = rest.split_at_checked(8).ok_or_else(invalid)?;
+        let limit = |bytes: &[u8]| -> Result<Option<usize>, StorageError> {
+            let limit = u64::from_be_bytes(bytes.try_into().map_err(|_| invalid())?);
+            Ok((limit != u64::MAX).then_some(limit as usize))
+        };
+        Ok(Self {
+            max_entries: limit(max_entries)?,
+            max_bytes: limit(max_bytes)?,
+            encrypt: *encrypt != 0,
+        })
+    }
+}
+
+/// Separate key space inside a store, from [`Storage::namespace`]. Keys never clash with
+/// those of other namespaces or of the store itself.
+#[derive(Clone)]
+pub struct Namespace {
+    storage: Storage,
+    name: String,
+    prefix: Vec<u8>,
+    cipher: Cipher,
+}
+
+impl Namespace {
+    /// Namespace `name` of `storage`, encrypting its values with `key` when configured to.
+    pub(crate) fn new(storage: Storage, name: &str, key: &[u8]) -> Result<Self, StorageError> {
+        validate_name(name)?;
+        Ok(Self {
+            storage,
+            name: name.to_string(),
+            prefix: entry_prefix(name),
+            cipher: Cipher::new(key),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config(&self) -> Result<NamespaceConfig, StorageError> {
+        self.storage
+            .read_with("namespace_config", None, |index| config(index, &self.name))
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let key = self.key(key);
+        self.storage.read_with("get", Some(&key), |index| {
+            let encrypt = config(index, &self.name)?.encrypt;
+            self.open(encrypt, index.get(&key)?)
+        })
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> Result<bool, StorageError> {
+        let key = self.key(key);
+        self.storage
+            .read_with("contains_key", Some(&key), |index| index.contains_key(&key))
+    }
+
+    pub fn insert(&self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
+        let key = self.key(key);
+        self.storage.write_with("insert", Some(&key), |index| {
+            let config = config(index, &self.name)?;
+            let value = match config.encrypt {
+                true => self.cipher.encrypt(&value)?,
+                false => value,
+            };
+
+            let (mut count, mut size) = index.prefix_usage(&self.prefix);
+            match index.get(&key) {
+                Ok(old) => size -= old.len(),
+                Err(_) => count += 1,
+            }
+            size += value.len();
+            if let Some(max) = config.max_entries.filter(|max| count > *max) {
+                return Err(self.over_quota(format!("{} entries", max)));
+            }
+            if let Some(max) = config.max_bytes.filter(|max| size > *max) {
+                return Err(self.over_quota(format!("{} bytes", max)));
+            }
+
+            index
+                .insert(key.clone(), value)?
+                .map(|old| self.open(config.encrypt, old))
+                .transpose()
+        })
+    }
+
+    pub fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        let key = self.key(key);
+        self.storage.write_with("remove", Some(&key), |index| {
+            let encrypt = config(index, &self.name)?.encrypt;
+            index
+                .remove(&key)?
+                .map(|old| self.open(encrypt, old))
+                .transpose()
+        })
+    }
+
+    /// Runs an ordered scan over the keys of the namespace. Bounds, prefixes and cursors
+    /// are all relative to the namespace.
+    pub fn scan(&self, scan: KeyScan) -> Result<Page, StorageError> {
+        self.storage.read_with("scan", None, |index| {
+            Ok(index
+                .scan(&scan.under(&self.prefix), |_| true)
+                .strip_prefix(&self.prefix))
+        })
+    }
+
+    /// Keys in the namespace, in order.
+    pub fn keys(&self) -> Result<Vec<Vec<u8>>, StorageError> {
+        self.storage.read_with("get_all_keys", None, |index| {
+            Ok(index
+                .scan(&KeyScan::prefix(&self.prefix), |_| true)
+                .strip_prefix(&self.prefix)
+                .keys)
+        })
+    }
+
+    pub fn len(&self) -> Result<usize, StorageError> {
+        self.storage.read_with("len", None, |index| {
+            Ok(index.count(|key| key.starts_with(&self.prefix)))
+        })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, StorageError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Writes the namespace's entries to a passphrase-protected archive, as plain keys and
+    /// values that can be imported into any store.
+    pub fn export(&self, path: impl AsRef<Path>, passphrase: &str) -> Result<(), StorageError> {
+        self.export_with_params(path, passphrase, KdfParams::default())
+    }
+
+    pub fn export_with_params(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<(), StorageError> {
+        self.storage.read_with("export", None, |index| {
+            // Read before export_prefix takes the store lock, which config would take again.
+            let encrypt = config(index, &self.name)?.encrypt;
+            let payload = index.export_prefix(&self.prefix, |value| self.open(encrypt, value))?;
+            Archive::seal_with_params(&payload, passphrase, kdf)?.write(path)?;
+            Ok(())
+        })
+    }
+
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    /// Turns a stored value back into the one that was inserted, `encrypt` being whether
+    /// the namespace encrypts its values.
+    fn open(&self, encrypt: bool, value: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        match encrypt {
+            true => Ok(self.cipher.decrypt(&value)?),
+            false => Ok(value),
+        }
+    }
+
+    fn over_quota(&self, limit: String) -> StorageError {
+        StorageError::QuotaExceeded {
+            namespace: self.name.clone(),
+            limit,
+        }
+    }
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<(), StorageError> {
+    if name.is_empty() || name.len() > 255 || name.contains('\0') {
+        return Err(StorageError::InvalidNamespace(format!(
+            "{:?} must be 1 to 255 bytes without NUL characters",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Fails for keys that only the store itself may use.
+pub(crate) fn user_key(key: &[u8]) -> Result<(), StorageError> {
+    match key.starts_with(RESERVED_PREFIX) {
+        true => Err(StorageError::ReservedKey),
+        false => Ok(()),
+    }
+}
+
+/// Info the key of namespace `name` is derived from the store's master key with.
+pub(crate) fn key_info(name: &str) -> Vec<u8> {
+    [b"zewos-namespace:", name.as_bytes()].concat()
+}
+
+pub(crate) fn entry_prefix(name: &str) -> Vec<u8> {
+    [ENTRY_PREFIX, name.as_bytes(), b"\0"].concat()
+}
+
+pub(crate) fn config_key(name: &str) -> Vec<u8> {
+    [CONFIG_PREFIX, name.as_bytes()].concat()
+}
+
+pub(crate) fn config(index: &StorageIndex, name: &str) -> Result<NamespaceConfig, StorageError> {
+    match index.get(&config_key(name)) {
+        Ok(bytes) => NamespaceConfig::from_bytes(&bytes),
+        Err(StorageError::KeyNotFound) => Ok(NamespaceConfig::default()),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn set_config(
+    index: &StorageIndex,
+    name: &str,
+    new: NamespaceConfig,
+) -> Result<(), StorageError> {
+    let old = config(index, name)?;
+    if old.encrypt != new.encrypt && index.prefix_usage(&entry_prefix(name)).0 > 0 {
+        return Err(StorageError::InvalidNamespace(format!(
+            "cannot change encryption of {:?} while it holds entries",
+            name
+        )));
+    }
+    index.insert(config_key(name), new.to_bytes())?;
+    Ok(())
+}
+
+/// Names of every namespace that holds entries or has a config.
+pub(crate) fn names(index: &StorageIndex) -> Vec<String> {
+    let mut names: Vec<String> = index
+        .keys_with_prefix(RESERVED_PREFIX)
+        .iter()
+        .filter_map(|key| {
+            let name = match key.strip_prefix(CONFIG_PREFIX) {
+                Some(name) => name,
+                None => {
+                    let rest = key.strip_prefix(ENTRY_PREFIX)?;
+                    &rest[..rest.iter().position(|byte| *byte == 0)?]
+                }
+            };
+            String::from_utf8(name.to_vec()).ok()
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_config_bytes() {
+        let config = NamespaceConfig::new()
+            .with_max_entries(10)
+            .with_encrypt(true);
+        assert_eq!(
+            NamespaceConfig::from_bytes(&config.to_bytes()).unwrap(),
+            config
+        );
+        assert_eq!(
+            NamespaceConfig::from_bytes(&NamespaceConfig::default().to_bytes()).unwrap(),
+            NamespaceConfig::default()
+        );
+        assert!(NamespaceConfig::from_bytes(b"\x01\x00").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use zewos_core::hash::Sha256;
+use zewos_core::metadata::state_hash;
+use zewos_core::signing::{self, Keypair};
+use zewos_storage::MerkleProof;
+
+/// Merkle root of a store at one generation, signed with the store's key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRoot {
+    pub generation: u64,
+    pub merkle_root: Sha256,
+    pub history_hash: Option<Sha256>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedRoot {
+    pub(crate) fn sign(
+        generation: u64,
+        merkle_root: Sha256,
+        history_hash: Option<Sha256>,
+        key: &Keypair,
+    ) -> Self {
+        let signature =
+            key.sign(state_hash(generation, &merkle_root, history_hash.as_ref()).as_bytes());
+        Self {
+            generation,
+            merkle_root,
+            history_hash,
+            signature,
+        }
+    }
+
+    /// Whether the signature verifies against `public_key`, from [`Storage::public_key`].
+    ///
+    /// [`Storage::public_key`]: crate::Storage::public_key
+    pub fn verify(&self, public_key: &[u8]) -> bool {
+        let hash = state_hash(
+            self.generation,
+            &self.merkle_root,
+            self.history_hash.as_ref(),
+        );
+        signing::verify(public_key, hash.as_bytes(), &self.signature).is_ok()
+    }
+}
+
+/// Proof that an entry belongs to a signed store state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedProof {
+    pub root: SignedRoot,
+    pub proof: MerkleProof,
+}
+
+impl SignedProof {
+    /// Whether `key` held `value` in a state signed by the holder of `public_key`.
+    pub fn verify(&self, public_key: &[u8], key: &[u8], value: &[u8]) -> bool {
+        self.root.verify(public_key) && self.proof.verify(&self.root.merkle_root, key, value)
+    }
+}
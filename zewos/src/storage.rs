@@ -1,13 +1,89 @@
-use super::config::ZewosConfig;
+use super::audit::{write_log, AuditLog};
+use super::check::{CheckReport, Issue, RepairReport};
+use super::config::{LogLevel, ReloadPolicy, RollbackPolicy, ZewosConfig};
+use super::generation::GenerationMark;
+use super::iter::{Entry, EntryIter, KeyIter, PairIter, ValueIter};
+use super::namespace::{self, Namespace, NamespaceConfig};
+use super::proof::{SignedProof, SignedRoot};
+use super::watch::Watcher;
+use std::cell::RefCell;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+use zewos_core::hash::Sha256;
+use zewos_core::metadata::BackupMetadata;
+use zewos_core::signing::Keypair;
+use zewos_dir::archive::Archive;
+pub use zewos_dir::archive::KdfParams;
 use zewos_dir::dir::Directory;
+use zewos_dir::file::File;
+use zewos_dir::lock::{FileLock, LockMode};
 use zewos_dir::logs::LogsManager;
+pub use zewos_dir::logs::{ChainError, ChainErrorKind, ChainReport, LogIter, LogQuery};
+pub use zewos_dir::logs::{ErrorKind, Log, Outcome};
+pub use zewos_dir::sink::AuditSink;
+#[cfg(unix)]
+pub use zewos_dir::sink::SyslogSink;
 use zewos_storage::{errors::StorageError, StorageIndex};
+pub use zewos_storage::{
+    ConflictPolicy, Cursor, KeyScan, MergeReport, MerkleProof, ObjectMetadata, Page, Snapshot,
+};
+/// Handle to a store. Clones are cheap and share the same store, so a `Storage` can be
+/// handed to many threads: reads run concurrently, writes are serialized.
+#[derive(Clone)]
 pub struct Storage {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
     index: StorageIndex,
     dir: Directory,
-    logger: LogsManager,
+    audit: AuditLog,
+    log_level: LogLevel,
+    rollback_policy: RollbackPolicy,
+    reload_policy: ReloadPolicy,
+    generation: GenerationMark,
+    /// Generation of the store files as of the last load, save or reload from this handle.
+    loaded: Mutex<u64>,
+    /// Held across a write and the save that follows it, so saves never interleave.
+    write_lock: Mutex<()>,
+    /// Signs the store state on every save.
+    state_key: Keypair,
+    /// Keeps other processes from opening the store for writing, `None` for a read-only
+    /// store. Dropped last, after the session has been closed.
+    writer_lock: Option<FileLock>,
+}
+
+thread_local! {
+    /// Id of the audit record of the last failed operation on this thread.
+    static LAST_ERROR_ID: RefCell<Option<String>> = const { RefCell::new(None) };
 }
 
+/// Operations that only read the store, left out of the audit log at [`LogLevel::Writes`]
+/// unless they fail.
+const READ_OPERATIONS: &[&str] = &[
+    "get",
+    "contains_key",
+    "len",
+    "is_empty",
+    "get_all_keys",
+    "content_digest",
+    "prove",
+    "signed_root",
+    "snapshot",
+    "scan",
+    "iter",
+    "keys",
+    "values",
+    "entries_with_metadata",
+    "namespaces",
+    "namespace_config",
+    "version",
+    "history",
+    "get_version",
+];
+
 impl Storage {
     pub fn init(origin: &str) -> Result<Self, StorageError> {
         Self::init_with_config(origin, ZewosConfig::default())
@@ -17,125 +93,990 @@ impl Storage {
         if path.exists() {
             return Self::load(path.to_str().unwrap(), config);
         }
+        #[cfg(feature = "tracing")]
+        let _span = super::audit::span("init", None).entered();
 
-        let index = StorageIndex::new(config.cache_config, config.clone().backup_config)?;
+        let index = StorageIndex::new(config.cache_config, config.backup_config)?;
         let dir = Directory::new(path.to_str().unwrap());
-        let mut logger = dir.clone().logger();
+        let writer_lock = dir
+            .writer_lock(config.lock_timeout)
+            .map_err(StorageError::from_lock)?;
+        let mut logger = dir
+            .clone()
+            .logger()
+            .with_key_redaction(config.key_redaction)
+            .with_config(config.logs_config);
 
         if config.logging {
             logger.start_session()?;
         }
 
-        logger.add_log("zewos_init", "init", "first_initialization")?;
-        Ok(Self {
+        let generation = GenerationMark::new(Path::new(origin));
+        generation.store(0)?;
+        let storage = Self::from_parts(
             index,
-            dir: dir.clone(),
+            dir,
             logger,
+            &config,
+            generation,
+            0,
+            Some(writer_lock),
+        )?;
+        storage.record(Log::new("init", Outcome::Success))?;
+        Ok(storage)
+    }
+
+    fn from_parts(
+        index: StorageIndex,
+        dir: Directory,
+        logger: LogsManager,
+        config: &ZewosConfig,
+        generation: GenerationMark,
+        loaded: u64,
+        writer_lock: Option<FileLock>,
+    ) -> std::io::Result<Self> {
+        let state_key = state_key(&dir)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                index,
+                dir,
+                audit: AuditLog::spawn(logger),
+                log_level: config.log_level,
+                rollback_policy: config.rollback_policy,
+                reload_policy: config.reload_policy,
+                generation,
+                loaded: Mutex::new(loaded),
+                write_lock: Mutex::new(()),
+                state_key,
+                writer_lock,
+            }),
         })
     }
 
-    pub fn save(&mut self) -> std::io::Result<()> {
-        let (data, metadata, config) = self.index.serialize_backup().unwrap();
-        self.dir.objs_file().write(&data).unwrap();
-        self.dir.metadata_file().write(&metadata).unwrap();
-        self.dir.config_file().write(&config).unwrap();
-        self.logger
-            .add_log("zewos_storage", "save", "backup_created")?;
+    /// Writes the store to disk, dropping any expired entries first.
+    pub fn save(&self) -> Result<(), StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("save", None).entered();
+        let _write = self.write_lock();
+        self.writable("save", None)?;
+        self.inner.index.purge_expired()?;
+        self.persist()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.inner.writer_lock.is_none()
+    }
+
+    /// Fails with [`StorageError::ReadOnly`], recording the refused `operation`, if the
+    /// store was opened read-only.
+    fn writable(&self, operation: &str, key: Option<&[u8]>) -> Result<(), StorageError> {
+        if !self.is_read_only() {
+            return Ok(());
+        }
+        let result = Err(StorageError::ReadOnly);
+        self.audit(operation, key, &result)?;
+        result
+    }
+
+    fn persist(&self) -> Result<(), StorageError> {
+        let inner = &self.inner;
+        let (data, metadata, config) = inner
+            .index
+            .serialize_backup_signed(|hash| inner.state_key.sign(hash.as_bytes()))?;
+        let data_lock = inner.dir.data_lock(LockMode::Exclusive)?;
+        inner.dir.objs_file().write(&data)?;
+        inner.dir.metadata_file().write(&metadata)?;
+        inner.dir.config_file().write(&config)?;
+        inner.generation.store(inner.index.generation())?;
+        *self.loaded() = inner.index.generation();
+        drop(data_lock);
+        self.record(Log::new("save", Outcome::Success))?;
         Ok(())
     }
 
+    fn write_lock(&self) -> MutexGuard<'_, ()> {
+        self.inner
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn loaded(&self) -> MutexGuard<'_, u64> {
+        self.inner
+            .loaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Loads the store kept in the `.zewos` folder at `origin`.
     pub fn load(origin: &str, config: ZewosConfig) -> Result<Self, StorageError> {
-        let dir = Directory::new(origin);
+        let path = Path::new(origin);
+        let dir = Directory::new(path);
+        let writer_lock = dir
+            .writer_lock(config.lock_timeout)
+            .map_err(StorageError::from_lock)?;
+        let mut logger = dir
+            .clone()
+            .logger()
+            .with_key_redaction(config.key_redaction)
+            .with_config(config.logs_config);
+        if config.logging {
+            logger.start_session()?;
+        }
+        let generation = GenerationMark::new(origin_of(path));
+        Self::open(dir, config, logger, generation, Some(writer_lock), "load")
+    }
+
+    /// Loads the store kept in the `.zewos` folder at `path` like [`Storage::load`], first
+    /// setting up its generation mark if it has none.
+    ///
+    /// The mark is kept next to the `.zewos` folder, so a store moved or restored as a
+    /// folder, or last written before marks were kept, has none and fails to load with
+    /// [`StorageError::MissingGenerationMark`]. Adopting it takes its current state as the
+    /// newest one, so only adopt a copy known to be the latest. A mark that exists is
+    /// still checked as usual.
+    pub fn adopt(path: &str, config: ZewosConfig) -> Result<Self, StorageError> {
+        GenerationMark::new(origin_of(Path::new(path))).store(0)?;
+        Self::load(path, config)
+    }
+
+    /// Opens the store kept in the `.zewos` folder at `path` for reading only. Nothing in the store is created or
+    /// changed, every method that would change it fails with [`StorageError::ReadOnly`], and
+    /// other processes can still open it for writing, with changes picked up according to
+    /// [`ZewosConfig::reload_policy`].
+    ///
+    /// Nothing is logged unless a log folder is set with [`Storage::with_log_dir`] or a sink
+    /// with [`Storage::with_audit_sink`].
+    ///
+    /// Fails for a store without a master key, which it gets the first time it is opened
+    /// for writing.
+    pub fn open_read_only(path: &str, config: ZewosConfig) -> Result<Self, StorageError> {
+        let path = Path::new(path);
+        let dir = Directory::open(path)?;
+        let logger = dir
+            .logger()
+            .with_key_redaction(config.key_redaction)
+            .with_config(config.logs_config);
+        let generation = GenerationMark::existing(origin_of(path));
+        Self::open(dir, config, logger, generation, None, "open_read_only")
+    }
+
+    fn open(
+        dir: Directory,
+        config: ZewosConfig,
+        mut logger: LogsManager,
+        generation: GenerationMark,
+        writer_lock: Option<FileLock>,
+        operation: &str,
+    ) -> Result<Self, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = super::audit::span(operation, None).entered();
+        let data_lock = dir.data_lock(LockMode::Shared)?;
         let data = dir.objs_file().read()?;
         let metadata = dir.metadata_file().read()?;
         let backup_config = dir.config_file().read()?;
+        drop(data_lock);
         let index =
             StorageIndex::deserialize_backup(data, metadata, backup_config, config.cache_config)?;
-        let mut logger = dir.clone().logger();
+        let unsigned =
+            match verify_state(&state_key(&dir)?, &index.get_metadata()?, dir.has_new_key()) {
+                Ok(unsigned) => unsigned,
+                Err(e) => {
+                    write_log(&mut logger, failure(operation, &e))?;
+                    return Err(e);
+                }
+            };
+
+        let found = index.generation();
+        let rollback = match generation.load()? {
+            Some(expected) if found < expected => Some(StorageError::Rollback { found, expected }),
+            // The mark is created with the store, so a store that has been written to but
+            // has no mark may have been put back along with a deleted one.
+            None if found > 0 => Some(StorageError::MissingGenerationMark { found }),
+            _ => None,
+        };
+        if let Some(e) = rollback {
+            match config.rollback_policy {
+                RollbackPolicy::Refuse => {
+                    write_log(&mut logger, failure(operation, &e))?;
+                    return Err(e);
+                }
+                RollbackPolicy::Warn => write_log(
+                    &mut logger,
+                    Log::new(operation, Outcome::Success).with_details("rollback_detected"),
+                )?,
+                RollbackPolicy::Ignore => {}
+            }
+            if let StorageError::Rollback { expected, .. } = e {
+                index.advance_generation(expected);
+            }
+        }
+        if writer_lock.is_some() {
+            generation.store(index.generation())?;
+        }
+
+        let storage =
+            Self::from_parts(index, dir, logger, &config, generation, found, writer_lock)?;
+        storage.record(Log::new(operation, Outcome::Success))?;
+        if unsigned && !storage.is_read_only() {
+            storage.persist()?;
+        }
+        Ok(storage)
+    }
+
+    /// Reports what is wrong with the store kept in the `.zewos` folder at `path`, without
+    /// creating or changing anything in it.
+    pub fn check(path: &str) -> Result<CheckReport, StorageError> {
+        let path = Path::new(path);
+        if !path.is_dir() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        let _data = Directory::existing_data_lock(path, LockMode::Shared)?;
+        let mut report = CheckReport::default();
+        let paths = Directory::file_paths(path);
+        let mut contents = Vec::new();
+        for path in &paths {
+            if !path.exists() {
+                report.issues.push(Issue::Unreadable {
+                    file: path.clone(),
+                    reason: "missing".to_string(),
+                });
+                contents.push(None);
+                continue;
+            }
+            let file = File::new(path.clone());
+            if !file.check_permissions()? {
+                report
+                    .issues
+                    .push(Issue::Permissions { file: path.clone() });
+            }
+            match file.read() {
+                Ok(data) => contents.push(Some(data)),
+                Err(e) => {
+                    report.issues.push(Issue::Unreadable {
+                        file: path.clone(),
+                        reason: e.to_string(),
+                    });
+                    contents.push(None);
+                }
+            }
+        }
+        let [Some(data), Some(metadata), Some(config)] = contents.as_slice() else {
+            // Check whatever could be read on its own.
+            let [data, metadata, config] = contents.as_slice() else {
+                unreachable!("a store has three files");
+            };
+            let errors =
+                StorageIndex::check_files(data.as_deref(), metadata.as_deref(), config.as_deref());
+            for (path, error) in paths.iter().zip(errors) {
+                if let Some(error) = error {
+                    report.issues.push(Issue::Corrupted {
+                        reason: format!("{}: {}", path.display(), error),
+                    });
+                }
+            }
+            return Ok(report);
+        };
+        match StorageIndex::check_backup(data, metadata, config) {
+            Ok(issues) => report.issues.extend(issues.into_iter().map(Issue::Backup)),
+            Err(e) => report.issues.push(Issue::Corrupted {
+                reason: e.to_string(),
+            }),
+        }
+        if report.is_ok() {
+            let index = StorageIndex::deserialize_backup(
+                data.clone(),
+                metadata.clone(),
+                config.clone(),
+                Default::default(),
+            )?;
+            let found = index.generation();
+            match GenerationMark::existing(origin_of(path)).load()? {
+                Some(expected) if found < expected => {
+                    report.issues.push(Issue::Rollback { found, expected })
+                }
+                None if found > 0 => report.issues.push(Issue::MissingGenerationMark { found }),
+                _ => {}
+            }
+        }
+        Ok(report)
+    }
+
+    /// Fixes file permissions of the store kept in the `.zewos` folder at `path` and
+    /// rewrites it from every entry that can still be read, recomputing its metadata. The
+    /// original files are copied aside first, and a store whose object file cannot be read
+    /// at all is left untouched.
+    pub fn repair(path: &str, config: ZewosConfig) -> Result<RepairReport, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = super::audit::span("repair", None).entered();
+        let path = Path::new(path);
+        if !path.exists() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        let dir = Directory::new(path);
+        let _writer = dir
+            .writer_lock(config.lock_timeout)
+            .map_err(StorageError::from_lock)?;
+        let _data = dir.data_lock(LockMode::Exclusive)?;
+        let mut report = RepairReport::default();
+        for file in dir.get_files() {
+            if !file.check_permissions()? {
+                file.fix_permissions()?;
+                report.permissions_fixed += 1;
+            }
+        }
+
+        // Rewriting the store without its objects would empty it.
+        let data = dir.objs_file().read()?;
+        let metadata = dir.metadata_file().read().unwrap_or_default();
+        let backup_config = dir.config_file().read().unwrap_or_default();
+        let (index, dropped) =
+            StorageIndex::recover_backup(&data, &metadata, &backup_config, config.cache_config)?;
+        report.originals = set_aside(&dir.get_handler().path, dir.get_files())?;
+        let generation = GenerationMark::new(origin_of(path));
+        index.advance_generation(generation.load().ok().flatten().unwrap_or(0));
+
+        let state_key = state_key(&dir)?;
+        let (data, metadata, backup_config) =
+            index.serialize_backup_signed(|hash| state_key.sign(hash.as_bytes()))?;
+        dir.objs_file().write(&data)?;
+        dir.metadata_file().write(&metadata)?;
+        dir.config_file().write(&backup_config)?;
+        generation.reset(index.generation())?;
+
+        report.recovered = index.len();
+        report.dropped = dropped;
+
+        let mut logger = dir
+            .logger()
+            .with_key_redaction(config.key_redaction)
+            .with_config(config.logs_config);
         if config.logging {
             logger.start_session()?;
         }
+        write_log(
+            &mut logger,
+            Log::new("repair", Outcome::Success).with_details(format!(
+                "recovered={} dropped={}",
+                report.recovered, dropped
+            )),
+        )?;
+        Ok(report)
+    }
 
-        logger.add_log("zewos_init", "load", "storage_loaded")?;
-        Ok(Self { index, dir, logger })
+    pub fn get(&self, key: &Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        self.read_with("get", Some(key), |index| {
+            namespace::user_key(key)?;
+            index.get(key)
+        })
     }
 
-    pub fn get(&mut self, key: &Vec<u8>) -> Result<Vec<u8>, StorageError> {
-        self.logger.add_log(
-            "zewos_request",
-            "get",
-            format!("key-\"{}\"", String::from_utf8(key.clone()).unwrap()).as_str(),
-        )?;
-        let result = self.index.get(key);
-        match &result {
-            Ok(_) => self.logger.add_log("zewos_request", "get", "success")?,
-            Err(_) => self.logger.add_log("zewos_request", "get", "failed")?,
-        }
-        result
+    pub fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
+        self.write_with("insert", Some(&key), |index| {
+            namespace::user_key(&key)?;
+            index.insert(key.clone(), value)
+        })
     }
 
-    pub fn insert(
-        &mut self,
+    /// Inserts `value` so that it expires after `ttl`. An expired entry is no longer
+    /// readable, [`Storage::get`] failing with [`StorageError::Expired`], and is removed
+    /// on the next save or sweep.
+    pub fn insert_with_ttl(
+        &self,
         key: Vec<u8>,
         value: Vec<u8>,
+        ttl: Duration,
     ) -> Result<Option<Vec<u8>>, StorageError> {
-        self.logger.add_log(
-            "zewos_request",
-            "insert",
-            format!("key-\"{}\"", String::from_utf8(key.clone()).unwrap()).as_str(),
-        )?;
-        let result = self.index.insert(key, value);
-        match &result {
-            Ok(_) => self.logger.add_log("zewos_request", "insert", "success")?,
-            Err(_) => self.logger.add_log("zewos_request", "insert", "failed")?,
+        self.write_with("insert_with_ttl", Some(&key), |index| {
+            namespace::user_key(&key)?;
+            index.insert_with_ttl(key.clone(), value, ttl)
+        })
+    }
+
+    /// Removes every expired entry, returning how many there were.
+    pub fn purge_expired(&self) -> Result<usize, StorageError> {
+        if !self.inner.index.has_expired() {
+            return Ok(0);
         }
-        self.save()?;
+        self.write_with("purge_expired", None, |index| {
+            index.purge_expired().map(|keys| keys.len())
+        })
+    }
+
+    pub fn remove(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
+        self.write_with("remove", Some(key), |index| {
+            namespace::user_key(key)?;
+            index.remove(key)
+        })
+    }
+
+    /// Sets `key` to `new` only if it currently holds `expected`, `None` meaning absent.
+    /// Fails with [`StorageError::Conflict`] carrying the current version otherwise, and
+    /// returns the new version on success.
+    pub fn compare_and_swap(
+        &self,
+        key: Vec<u8>,
+        expected: Option<&[u8]>,
+        new: Vec<u8>,
+    ) -> Result<u64, StorageError> {
+        self.write_with("compare_and_swap", Some(&key), |index| {
+            namespace::user_key(&key)?;
+            index.compare_and_swap(key.clone(), expected, new)
+        })
+    }
+
+    pub fn insert_if_absent(&self, key: Vec<u8>, value: Vec<u8>) -> Result<u64, StorageError> {
+        self.write_with("insert_if_absent", Some(&key), |index| {
+            namespace::user_key(&key)?;
+            index.insert_if_absent(key.clone(), value)
+        })
+    }
+
+    /// Replaces the value of `key` if it is still at `expected_version`, as returned by
+    /// [`Storage::version`] or a previous conditional write.
+    pub fn update(
+        &self,
+        key: Vec<u8>,
+        expected_version: u64,
+        value: Vec<u8>,
+    ) -> Result<u64, StorageError> {
+        self.write_with("update", Some(&key), |index| {
+            namespace::user_key(&key)?;
+            index.update(key.clone(), expected_version, value)
+        })
+    }
+
+    /// Version of the entry under `key`: the generation of the write that last set it.
+    pub fn version(&self, key: &[u8]) -> Result<u64, StorageError> {
+        self.read_with("version", Some(key), |index| {
+            namespace::user_key(key)?;
+            index.version(key)
+        })
+    }
+
+    /// Values `key` held before its current one, newest first. How many are kept is set
+    /// with [`BackupConfig::with_history_limit`] when the store is created.
+    /// A removed key keeps its history, its last value first, so [`Storage::revert`] can
+    /// bring it back.
+    ///
+    /// [`BackupConfig::with_history_limit`]: zewos_storage::BackupConfig::with_history_limit
+    pub fn history(&self, key: &[u8]) -> Result<Vec<Entry>, StorageError> {
+        self.read_with("history", Some(key), |index| {
+            namespace::user_key(key)?;
+            Ok(index
+                .history(key)?
+                .into_iter()
+                .map(|(value, metadata)| Entry {
+                    key: key.to_vec(),
+                    value,
+                    metadata,
+                })
+                .collect())
+        })
+    }
+
+    /// Value of `key` `n` writes ago, `0` being the current one. Fails with
+    /// [`StorageError::VersionNotFound`] past the retained history.
+    pub fn get_version(&self, key: &[u8], n: usize) -> Result<Vec<u8>, StorageError> {
+        self.read_with("get_version", Some(key), |index| {
+            namespace::user_key(key)?;
+            index.get_version(key, n)
+        })
+    }
+
+    /// Makes the value `key` held `n` writes ago its current value again. The value being
+    /// replaced joins the history like any other overwrite.
+    pub fn revert(&self, key: Vec<u8>, n: usize) -> Result<u64, StorageError> {
+        self.write_with("revert", Some(&key), |index| {
+            namespace::user_key(&key)?;
+            index.revert(key.clone(), n)
+        })
+    }
+
+    pub fn contains_key(&self, key: &Vec<u8>) -> Result<bool, StorageError> {
+        self.read_with("contains_key", Some(key), |index| {
+            namespace::user_key(key)?;
+            index.contains_key(key)
+        })
+    }
+
+    /// Number of entries outside of namespaces.
+    pub fn len(&self) -> usize {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("len", None).entered();
+        self.refresh().unwrap_or(());
+        self.record(Log::new("len", Outcome::Success)).unwrap_or(());
+        self.inner
+            .index
+            .count(|key| namespace::user_key(key).is_ok())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("is_empty", None).entered();
+        self.refresh().unwrap_or(());
+        self.record(Log::new("is_empty", Outcome::Success))
+            .unwrap_or(());
+        self.inner
+            .index
+            .count(|key| namespace::user_key(key).is_ok())
+            == 0
+    }
+
+    /// Runs an ordered scan over the keys outside of namespaces.
+    pub fn scan(&self, scan: KeyScan) -> Result<Page, StorageError> {
+        self.read_with("scan", None, |index| {
+            Ok(index.scan(&scan, |key| namespace::user_key(key).is_ok()))
+        })
+    }
+
+    /// Keys starting with `prefix`, in order.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>, StorageError> {
+        Ok(self.scan(KeyScan::prefix(prefix))?.keys)
+    }
+
+    /// Keys within `range`, in order.
+    pub fn range(&self, range: impl RangeBounds<Vec<u8>>) -> Result<Vec<Vec<u8>>, StorageError> {
+        Ok(self.scan(KeyScan::range(range))?.keys)
+    }
+
+    /// Lazily walks the keys outside of namespaces, in order.
+    pub fn keys(&self) -> Result<KeyIter, StorageError> {
+        self.read_with("keys", None, |_| Ok(KeyIter::new(self.clone())))
+    }
+
+    /// Lazily walks the entries outside of namespaces as `(key, value)` pairs, in key order.
+    pub fn iter(&self) -> Result<PairIter, StorageError> {
+        self.read_with("iter", None, |_| Ok(PairIter::new(self.clone())))
+    }
+
+    /// Lazily walks the values outside of namespaces, in key order.
+    pub fn values(&self) -> Result<ValueIter, StorageError> {
+        self.read_with("values", None, |_| Ok(ValueIter::new(self.clone())))
+    }
+
+    /// Lazily walks the entries outside of namespaces with their metadata, in key order.
+    pub fn entries_with_metadata(&self) -> Result<EntryIter, StorageError> {
+        self.read_with("entries_with_metadata", None, |_| {
+            Ok(EntryIter::new(self.clone()))
+        })
+    }
+
+    /// Keys of the entries outside of namespaces, in order.
+    pub fn get_all_keys(&self) -> Result<Vec<Vec<u8>>, StorageError> {
+        self.read_with("get_all_keys", None, |index| {
+            let mut keys = index.get_all_keys()?;
+            keys.retain(|key| namespace::user_key(key).is_ok());
+            Ok(keys)
+        })
+    }
+
+    /// Handle to the namespace `name`. Namespaces need no creating: one exists as soon as
+    /// it holds an entry or has a config.
+    pub fn namespace(&self, name: &str) -> Result<Namespace, StorageError> {
+        let key = self
+            .inner
+            .dir
+            .master_key()
+            .derive(&namespace::key_info(name));
+        Namespace::new(self.clone(), name, &key)
+    }
+
+    pub fn configure_namespace(
+        &self,
+        name: &str,
+        config: NamespaceConfig,
+    ) -> Result<(), StorageError> {
+        namespace::validate_name(name)?;
+        self.write_with("configure_namespace", None, |index| {
+            namespace::set_config(index, name, config)
+        })
+    }
+
+    pub fn namespaces(&self) -> Result<Vec<String>, StorageError> {
+        self.read_with("namespaces", None, |index| Ok(namespace::names(index)))
+    }
+
+    /// Removes every entry of the namespace `name` along with its config, returning how
+    /// many entries there were.
+    pub fn delete_namespace(&self, name: &str) -> Result<usize, StorageError> {
+        namespace::validate_name(name)?;
+        self.write_with("delete_namespace", None, |index| {
+            let keys = index.keys_with_prefix(&namespace::entry_prefix(name));
+            for key in &keys {
+                index.remove(key)?;
+            }
+            index.remove(&namespace::config_key(name))?;
+            Ok(keys.len())
+        })
+    }
+
+    pub(crate) fn index(&self) -> &StorageIndex {
+        &self.inner.index
+    }
+
+    /// Runs a read against the index, after reloading if configured to, and records it.
+    pub(crate) fn read_with<T>(
+        &self,
+        operation: &str,
+        key: Option<&[u8]>,
+        read: impl FnOnce(&StorageIndex) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span(operation, key).entered();
+        let result = self.refresh().and_then(|_| read(&self.inner.index));
+        self.audit(operation, key, &result)?;
         result
     }
 
-    pub fn remove(&mut self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, StorageError> {
-        self.logger.add_log(
-            "zewos_request",
-            "remove",
-            format!("key-\"{}\"", String::from_utf8(key.clone()).unwrap()).as_str(),
-        )?;
-        let result = self.index.remove(key);
-        match &result {
-            Ok(_) => self.logger.add_log("zewos_request", "remove", "success")?,
-            Err(_) => self.logger.add_log("zewos_request", "remove", "failed")?,
+    /// Runs a write against the index under the write lock, records it and saves the store.
+    pub(crate) fn write_with<T>(
+        &self,
+        operation: &str,
+        key: Option<&[u8]>,
+        write: impl FnOnce(&StorageIndex) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span(operation, key).entered();
+        let _write = self.write_lock();
+        self.writable(operation, key)?;
+        let result = write(&self.inner.index);
+        self.audit(operation, key, &result)?;
+        let value = result?;
+        self.persist()?;
+        Ok(value)
+    }
+
+    pub fn content_digest(&self) -> Sha256 {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("content_digest", None).entered();
+        self.refresh().unwrap_or(());
+        self.record(Log::new("content_digest", Outcome::Success))
+            .unwrap_or(());
+        self.inner.index.content_digest()
+    }
+
+    /// Point-in-time view of the store, for reading several keys consistently while writers
+    /// carry on. Only taking the snapshot is audited, not the reads made through it.
+    /// Namespaced entries are left out, as they are from [`Storage::len`].
+    pub fn snapshot(&self) -> Result<Snapshot, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("snapshot", None).entered();
+        let result = self.refresh().map(|_| {
+            self.inner
+                .index
+                .snapshot()
+                .hiding(namespace::RESERVED_PREFIX)
+        });
+        self.audit("snapshot", None, &result)?;
+        result
+    }
+
+    /// Verifies the hash chain and signatures of every audit log session.
+    pub fn verify_logs(&self) -> std::io::Result<ChainReport> {
+        self.inner.audit.lock().logger.verify_chain()
+    }
+
+    /// Streams audit records from every session that match `query`.
+    pub fn query_logs(&self, query: LogQuery) -> std::io::Result<LogIter> {
+        self.inner.audit.lock().logger.query(query)
+    }
+
+    /// Proof that `key` holds its current value, tied to the signed state of the store.
+    pub fn prove(&self, key: &[u8]) -> Result<SignedProof, StorageError> {
+        self.read_with("prove", Some(key), |index| {
+            namespace::user_key(key)?;
+            let (proof, metadata) = index.prove(key)?;
+            Ok(SignedProof {
+                root: self.sign_root(&metadata),
+                proof,
+            })
+        })
+    }
+
+    /// Merkle root of the store as it is now, signed with the store's key.
+    pub fn signed_root(&self) -> Result<SignedRoot, StorageError> {
+        self.read_with("signed_root", None, |index| {
+            Ok(self.sign_root(&index.get_metadata()?))
+        })
+    }
+
+    /// Public half of the key store states are signed with, to check a [`SignedRoot`].
+    pub fn public_key(&self) -> Vec<u8> {
+        self.inner.state_key.public_key()
+    }
+
+    fn sign_root(&self, metadata: &BackupMetadata) -> SignedRoot {
+        SignedRoot::sign(
+            metadata.generation,
+            metadata
+                .merkle_root
+                .unwrap_or_else(|| self.inner.index.content_digest()),
+            metadata.history_hash,
+            &self.inner.state_key,
+        )
+    }
+
+    /// Reloads the store if its files are at another generation than when this handle last
+    /// loaded, saved or reloaded it. Returns whether anything was reloaded.
+    pub fn reload(&self) -> Result<bool, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("reload", None).entered();
+        let result = self.reload_changed();
+        match result {
+            Ok(false) => {}
+            _ => self.audit("reload", None, &result)?,
         }
-        self.save()?;
         result
     }
 
-    pub fn contains_key(&mut self, key: &Vec<u8>) -> Result<bool, StorageError> {
-        self.logger.add_log(
-            "zewos_request",
-            "contains_key",
-            format!("key-\"{}\"", String::from_utf8(key.clone()).unwrap()).as_str(),
-        )?;
-        self.index.contains_key(key)
+    fn reload_changed(&self) -> Result<bool, StorageError> {
+        let _write = self.write_lock();
+        let inner = &self.inner;
+        let data_lock = inner.dir.data_lock(LockMode::Shared)?;
+        let metadata = inner.dir.metadata_file().read()?;
+        let stored = StorageIndex::stored_generation(&metadata)?;
+        let mut loaded = self.loaded();
+        if *loaded == stored {
+            return Ok(false);
+        }
+        let data = inner.dir.objs_file().read()?;
+        let backup_config = inner.dir.config_file().read()?;
+        drop(data_lock);
+
+        let min_generation = match inner.rollback_policy {
+            RollbackPolicy::Refuse => inner
+                .index
+                .generation()
+                .max(inner.generation.load()?.unwrap_or(0)),
+            RollbackPolicy::Warn | RollbackPolicy::Ignore => 0,
+        };
+        inner
+            .index
+            .reload_backup(data, metadata, backup_config, min_generation, |metadata| {
+                verify_state(&inner.state_key, metadata, false).map(drop)
+            })?;
+        *loaded = stored;
+        Ok(true)
     }
 
-    pub fn len(&mut self) -> usize {
-        self.logger
-            .add_log("zewos_request", "len", "queried_length")
-            .unwrap_or(());
-        self.index.len()
+    /// Reloads a read-only handle under [`ReloadPolicy::OnRead`]. A writable handle holds
+    /// the writer lock, so nobody else can change the files under it.
+    fn refresh(&self) -> Result<(), StorageError> {
+        if self.inner.reload_policy == ReloadPolicy::OnRead && self.is_read_only() {
+            self.reload()?;
+        }
+        Ok(())
     }
 
-    pub fn is_empty(&mut self) -> bool {
-        self.logger
-            .add_log("zewos_request", "is_empty", "checked_emptiness")
-            .unwrap_or(());
-        self.index.is_empty()
+    /// Starts a thread that calls [`Storage::reload`] every `interval` for as long as the
+    /// returned [`Watcher`] and at least one handle to the store are alive.
+    pub fn watch(&self, interval: Duration) -> Watcher {
+        let inner = Arc::downgrade(&self.inner);
+        Watcher::spawn(interval, move || match inner.upgrade() {
+            Some(inner) => {
+                let _ = Storage { inner }.reload();
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Starts a thread that calls [`Storage::purge_expired`] every `interval`, for as long
+    /// as the returned [`Watcher`] and at least one handle to the store are alive. A
+    /// read-only store is left alone, its expired entries are only hidden.
+    pub fn sweep(&self, interval: Duration) -> Watcher {
+        let inner = Arc::downgrade(&self.inner);
+        Watcher::spawn(interval, move || match inner.upgrade() {
+            Some(inner) => {
+                let storage = Storage { inner };
+                if !storage.is_read_only() {
+                    let _ = storage.purge_expired();
+                }
+                true
+            }
+            None => false,
+        })
+    }
+
+    fn audit<T>(
+        &self,
+        operation: &str,
+        key: Option<&[u8]>,
+        result: &Result<T, StorageError>,
+    ) -> std::io::Result<()> {
+        let mut log = match result {
+            Ok(_) => Log::new(operation, Outcome::Success),
+            Err(e) => failure(operation, e),
+        };
+        if let Some(key) = key {
+            log = log.with_key_id(self.inner.audit.key_label(key));
+        }
+        self.record(log)
     }
 
-    pub fn get_all_keys(&mut self) -> Result<Vec<Vec<u8>>, StorageError> {
-        self.logger
-            .add_log("zewos_request", "get_all_keys", "retrieved_all_keys")?;
-        self.index.get_all_keys()
+    /// Id of the audit record written for the last operation that failed on this thread, to
+    /// find the record with [`LogQuery::with_error_id`].
+    pub fn last_error_id() -> Option<String> {
+        LAST_ERROR_ID.with_borrow(Clone::clone)
     }
+
+    pub(crate) fn set_last_error_id(error_id: Option<String>) {
+        LAST_ERROR_ID.set(error_id);
+    }
+
+    /// Writes `log`, except that successful reads are only queued, leaving the log I/O to
+    /// the audit writer. A queued record that cannot be written fails the next operation
+    /// that waits for its own record.
+    fn record(&self, log: Log) -> std::io::Result<()> {
+        let read = log.outcome() == Outcome::Success && READ_OPERATIONS.contains(&log.operation());
+        match (read, self.inner.log_level) {
+            (true, LogLevel::Writes) => Ok(()),
+            (true, LogLevel::All) => self.inner.audit.record_later(log),
+            (false, _) => self.inner.audit.record(log),
+        }
+    }
+
+    /// Keeps this handle's audit log in `path` instead of the store's `logs` folder, which
+    /// is how a read-only store can still log its reads.
+    pub fn with_log_dir(self, path: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        {
+            let mut audit = self.inner.audit.lock();
+            let mut logger = LogsManager::in_folder(path.into(), self.inner.dir.master_key())?
+                .with_key_redaction(audit.logger.key_redaction())
+                .with_config(audit.logger.config());
+            logger.start_session()?;
+            audit.logger.end_session()?;
+            audit.logger = logger;
+        }
+        Ok(self)
+    }
+
+    /// Sends audit records to `sink` instead of the store's own log files.
+    pub fn with_audit_sink(self, sink: impl AuditSink + 'static) -> Self {
+        let mut audit = self.inner.audit.lock();
+        audit.sink = Some(Box::new(sink));
+        audit.fallback = false;
+        drop(audit);
+        self
+    }
+
+    /// Sends audit records to the syslog socket at `socket_path` (usually `/dev/log`),
+    /// writing them to the store's log files while the socket is unavailable. Records that
+    /// can be written to neither fail the operation.
+    #[cfg(unix)]
+    pub fn with_syslog(self, socket_path: impl Into<std::path::PathBuf>) -> Self {
+        let sink = SyslogSink::without_fallback().with_socket_path(socket_path);
+        let storage = self.with_audit_sink(sink);
+        storage.inner.audit.lock().fallback = true;
+        storage
+    }
+
+    /// Writes the store's entries and their history to a passphrase-protected archive.
+    /// Namespaces are left out, as their values are encrypted for this store; export them
+    /// with [`Namespace::export`] instead.
+    pub fn export(&self, path: impl AsRef<Path>, passphrase: &str) -> Result<(), StorageError> {
+        self.export_with_params(path, passphrase, KdfParams::default())
+    }
+
+    pub fn export_with_params(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<(), StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("export", None).entered();
+        let result = self
+            .inner
+            .index
+            .export_backup(|key| namespace::user_key(key).is_ok())
+            .and_then(|payload| {
+                Archive::seal_with_params(&payload, passphrase, kdf)?.write(path)?;
+                Ok(())
+            });
+        self.audit("export", None, &result)?;
+        result
+    }
+
+    /// Merges the entries of an archive from [`Storage::export`] into the store. Fails with
+    /// [`StorageError::ReservedKey`] without changing anything if the archive holds keys
+    /// only the store itself may use.
+    pub fn import(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+        conflict_policy: ConflictPolicy,
+    ) -> Result<MergeReport, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.inner.audit.span("import", None).entered();
+        self.writable("import", None)?;
+        let payload = Archive::read(path)?.open(passphrase)?;
+        let _write = self.write_lock();
+        let result = self
+            .inner
+            .index
+            .import_backup(&payload, conflict_policy, namespace::user_key);
+        self.audit("import", None, &result)?;
+        let report = result?;
+        self.persist()?;
+        Ok(report)
+    }
+}
+
+/// Folder holding the `.zewos` folder at `path`, where its generation mark is kept.
+fn origin_of(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new(""))
+}
+
+/// Copies the store `files` into a new folder under `repair` in `path`, returning it. They
+/// are copied as they are, encrypted for their original location, so they can be moved
+/// back there.
+fn set_aside(path: &Path, files: &[File]) -> std::io::Result<PathBuf> {
+    let since_epoch = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let folder = path
+        .join("repair")
+        .join(since_epoch.as_micros().to_string());
+    std::fs::create_dir_all(&folder)?;
+    for file in files {
+        if let Some(name) = file.path().file_name() {
+            std::fs::copy(file.path(), folder.join(name))?;
+        }
+    }
+    Ok(folder)
+}
+
+/// Key the state of the store in `dir` is signed with.
+fn state_key(dir: &Directory) -> std::io::Result<Keypair> {
+    dir.master_key().keypair(b"zewos-store-state")
+}
+
+/// Checks that `metadata` was signed with `key`. A store saved before it had a key is
+/// unsigned, and accepted only if `allow_unsigned`. Returns whether it was unsigned.
+fn verify_state(
+    key: &Keypair,
+    metadata: &BackupMetadata,
+    allow_unsigned: bool,
+) -> Result<bool, StorageError> {
+    let Some(state_hash) = metadata.state_hash else {
+        // Never saved, so there is nothing to sign.
+        return Ok(false);
+    };
+    match &metadata.state_signature {
+        Some(signature) if key.verify(state_hash.as_bytes(), signature).is_ok() => Ok(false),
+        None if allow_unsigned => Ok(true),
+        _ => Err(StorageError::StateSignature),
+    }
+}
+
+/// Audit record of `operation` failing with `error`, kept as this thread's
+/// [`Storage::last_error_id`].
+fn failure(operation: &str, error: &StorageError) -> Log {
+    let log = Log::new(operation, Outcome::Failure).with_error(error.kind());
+    Storage::set_last_error_id(log.error_id().map(str::to_string));
+    log
 }
 
 #[cfg(test)]
@@ -147,7 +1088,7 @@ mod tests {
     fn test_storage_init() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
         assert!(storage.is_empty());
     }
 
@@ -155,22 +1096,326 @@ mod tests {
     fn test_storage_load() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
 
         let key = b"key1".to_vec();
         let value = vec![1, 2, 3];
         storage.insert(key.clone(), value.clone()).unwrap();
+        drop(storage);
 
-        let mut loaded_storage = Storage::load(origin, ZewosConfig::default()).unwrap();
+        let loaded_storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
 
         assert_eq!(loaded_storage.get(&key).unwrap(), value);
     }
 
+    fn store_path(origin: &str) -> String {
+        Path::new(origin)
+            .join(".zewos")
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    const STORE_FILES: [&str; 3] = ["objects/objects.bin", "metadata.zewos", "config.zewos"];
+
+    fn snapshot(origin: &str) -> Vec<Vec<u8>> {
+        let store = Path::new(origin).join(".zewos");
+        STORE_FILES
+            .iter()
+            .map(|file| std::fs::read(store.join(file)).unwrap())
+            .collect()
+    }
+
+    fn restore(origin: &str, snapshot: &[Vec<u8>]) {
+        let store = Path::new(origin).join(".zewos");
+        for (file, contents) in STORE_FILES.iter().zip(snapshot) {
+            std::fs::write(store.join(file), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_storage_reload() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let config = ZewosConfig::default().with_rollback_policy(RollbackPolicy::Ignore);
+        let storage = Storage::init_with_config(origin, config).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+        let first = snapshot(origin);
+        storage.insert(b"other".to_vec(), vec![2]).unwrap();
+        let second = snapshot(origin);
+        assert!(!storage.reload().unwrap());
+
+        restore(origin, &first);
+        assert!(storage.reload().unwrap());
+        assert!(storage.get(&b"other".to_vec()).is_err());
+        assert_eq!(storage.len(), 1);
+        restore(origin, &second);
+        assert!(storage.reload().unwrap());
+        assert_eq!(storage.get(&b"other".to_vec()).unwrap(), vec![2]);
+        drop(storage);
+
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        restore(origin, &first);
+        assert!(matches!(
+            storage.reload(),
+            Err(StorageError::Rollback { .. })
+        ));
+    }
+
+    #[test]
+    fn test_storage_watch() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let config = ZewosConfig::default().with_rollback_policy(RollbackPolicy::Ignore);
+        let storage = Storage::init_with_config(origin, config).unwrap();
+        let empty = snapshot(origin);
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+
+        restore(origin, &empty);
+        assert_eq!(storage.len(), 1);
+
+        let _watcher = storage.watch(Duration::from_millis(10));
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !storage.is_empty() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn test_storage_read_only_needs_master_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+        drop(storage);
+        std::fs::remove_file(Path::new(&store_path(origin)).join("master.key")).unwrap();
+
+        assert!(matches!(
+            Storage::open_read_only(&store_path(origin), ZewosConfig::default()),
+            Err(StorageError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+        assert!(!Path::new(&store_path(origin)).join("master.key").exists());
+    }
+
+    #[test]
+    fn test_storage_read_only() {
+        fn listing(path: &Path) -> Vec<(std::path::PathBuf, u64, SystemTime)> {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(path).unwrap() {
+                let entry = entry.unwrap();
+                let metadata = entry.metadata().unwrap();
+                if metadata.is_dir() {
+                    entries.extend(listing(&entry.path()));
+                }
+                entries.push((entry.path(), metadata.len(), metadata.modified().unwrap()));
+            }
+            entries.sort();
+            entries
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let missing = temp_dir.path().join("missing");
+        assert!(Storage::open_read_only(missing.to_str().unwrap(), Default::default()).is_err());
+        assert!(!missing.exists());
+
+        let writer = Storage::init(origin).unwrap();
+        writer.insert(b"key".to_vec(), vec![1]).unwrap();
+        let before = listing(temp_dir.path());
+
+        let reader = Storage::open_read_only(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert!(reader.is_read_only());
+        assert_eq!(reader.get(&b"key".to_vec()).unwrap(), vec![1]);
+        assert!(matches!(
+            reader.insert(b"key".to_vec(), vec![2]),
+            Err(StorageError::ReadOnly)
+        ));
+        assert!(matches!(
+            reader.remove(&b"key".to_vec()),
+            Err(StorageError::ReadOnly)
+        ));
+        assert!(matches!(reader.save(), Err(StorageError::ReadOnly)));
+        drop(reader);
+        assert_eq!(listing(temp_dir.path()), before);
+
+        let logs_dir = TempDir::new().unwrap();
+        let config = ZewosConfig::default().with_reload_policy(ReloadPolicy::OnRead);
+        let reader = Storage::open_read_only(&store_path(origin), config)
+            .unwrap()
+            .with_log_dir(logs_dir.path())
+            .unwrap();
+        writer.insert(b"other".to_vec(), vec![2]).unwrap();
+        assert_eq!(reader.get(&b"other".to_vec()).unwrap(), vec![2]);
+        let reads = reader
+            .query_logs(LogQuery::new().with_operation("get"))
+            .unwrap()
+            .count();
+        assert_eq!(reads, 1);
+    }
+
+    #[test]
+    fn test_storage_busy() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+
+        assert!(matches!(Storage::init(origin), Err(StorageError::Busy)));
+        assert!(matches!(
+            Storage::repair(&store_path(origin), ZewosConfig::default()),
+            Err(StorageError::Busy)
+        ));
+        assert!(Storage::check(&store_path(origin)).unwrap().is_ok());
+
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(storage);
+        });
+        let config = ZewosConfig::default().with_lock_timeout(Duration::from_secs(5));
+        let storage = Storage::init_with_config(origin, config).unwrap();
+        release.join().unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_storage_rollback() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+
+        let files = ["objects/objects.bin", "metadata.zewos", "config.zewos"];
+        let store = temp_dir.path().join(".zewos");
+        let old: Vec<_> = files
+            .iter()
+            .map(|file| std::fs::read(store.join(file)).unwrap())
+            .collect();
+
+        storage.insert(b"key".to_vec(), vec![2]).unwrap();
+        drop(storage);
+        for (file, contents) in files.iter().zip(old) {
+            std::fs::write(store.join(file), contents).unwrap();
+        }
+
+        assert!(matches!(
+            Storage::load(&store_path(origin), ZewosConfig::default()),
+            Err(StorageError::Rollback {
+                found: 1,
+                expected: 2
+            })
+        ));
+
+        let config = ZewosConfig::default().with_rollback_policy(RollbackPolicy::Warn);
+        let storage = Storage::load(&store_path(origin), config).unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![1]);
+        storage.insert(b"key".to_vec(), vec![3]).unwrap();
+        drop(storage);
+
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn test_storage_missing_generation_mark() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        drop(storage);
+        let mark = temp_dir.path().join(".zewos-generation");
+        std::fs::remove_file(&mark).unwrap();
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+        drop(storage);
+
+        std::fs::remove_file(&mark).unwrap();
+        assert!(matches!(
+            Storage::load(&store_path(origin), ZewosConfig::default()),
+            Err(StorageError::MissingGenerationMark { found: 1 })
+        ));
+        assert!(Storage::check(&store_path(origin))
+            .unwrap()
+            .issues
+            .contains(&Issue::MissingGenerationMark { found: 1 }));
+
+        let config = ZewosConfig::default().with_rollback_policy(RollbackPolicy::Warn);
+        drop(Storage::load(&store_path(origin), config).unwrap());
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![1]);
+        drop(storage);
+
+        // A store moved without its mark is adopted as it is.
+        std::fs::remove_file(&mark).unwrap();
+        drop(Storage::adopt(&store_path(origin), ZewosConfig::default()).unwrap());
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![1]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_storage_check_and_repair() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        storage.insert(b"key1".to_vec(), vec![1]).unwrap();
+        storage.insert(b"key2".to_vec(), vec![2]).unwrap();
+        drop(storage);
+        assert!(Storage::check(&store_path(origin)).unwrap().is_ok());
+
+        let dir = Directory::new(temp_dir.path().join(".zewos"));
+        let mut metadata: serde_json::Value =
+            serde_json::from_slice(&dir.metadata_file().read().unwrap()).unwrap();
+        metadata["object_count"] = 5.into();
+        dir.metadata_file()
+            .write(&serde_json::to_vec(&metadata).unwrap())
+            .unwrap();
+        let config_path = dir.config_file().path().clone();
+        std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let report = Storage::check(&store_path(origin)).unwrap();
+        assert!(report
+            .issues
+            .contains(&Issue::Permissions { file: config_path }));
+        assert!(report
+            .issues
+            .contains(&Issue::Backup(zewos_storage::BackupIssue::ObjectCount {
+                recorded: 5,
+                actual: 2
+            })));
+
+        let original = std::fs::read(dir.metadata_file().path()).unwrap();
+        let repaired = Storage::repair(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert_eq!(repaired.permissions_fixed, 1);
+        assert_eq!(repaired.recovered, 2);
+        assert!(Storage::check(&store_path(origin)).unwrap().is_ok());
+        let copy = std::fs::read(repaired.originals.join("metadata.zewos")).unwrap();
+        assert_eq!(copy, original);
+
+        std::fs::write(dir.objs_file().path(), b"garbage").unwrap();
+        let report = Storage::check(&store_path(origin)).unwrap();
+        assert!(matches!(report.issues[..], [Issue::Unreadable { .. }]));
+
+        assert!(Storage::repair(&store_path(origin), ZewosConfig::default()).is_err());
+        assert_eq!(std::fs::read(dir.objs_file().path()).unwrap(), b"garbage");
+
+        std::fs::remove_file(dir.objs_file().path()).unwrap();
+        dir.config_file().write(b"garbage").unwrap();
+        let report = Storage::check(&store_path(origin)).unwrap();
+        assert!(!dir.objs_file().path().exists());
+        assert_eq!(report.issues.len(), 2);
+        assert!(matches!(
+            &report.issues[1],
+            Issue::Corrupted { reason } if reason.contains("config.zewos")
+        ));
+    }
+
     #[test]
     fn test_storage_insert_and_get() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
 
         let key = b"key2".to_vec();
         let value = vec![4, 5, 6];
@@ -183,7 +1428,7 @@ mod tests {
     fn test_storage_remove() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
 
         let key = b"key3".to_vec();
         let value = vec![7, 8, 9];
@@ -197,7 +1442,7 @@ mod tests {
     fn test_storage_contains_key() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
 
         let key = b"key4".to_vec();
         let value = vec![10, 11, 12];
@@ -211,7 +1456,7 @@ mod tests {
     fn test_storage_len_and_is_empty() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
 
         assert!(storage.is_empty());
         assert_eq!(storage.len(), 0);
@@ -226,7 +1471,7 @@ mod tests {
     fn test_storage_get_all_keys() {
         let temp_dir = TempDir::new().unwrap();
         let origin = temp_dir.path().to_str().unwrap();
-        let mut storage = Storage::init(origin).unwrap();
+        let storage = Storage::init(origin).unwrap();
 
         let keys = vec![b"key6".to_vec(), b"key7".to_vec(), b"key8".to_vec()];
         for (i, key) in keys.iter().enumerate() {
@@ -239,4 +1484,798 @@ mod tests {
             assert!(all_keys.contains(&key));
         }
     }
+
+    #[test]
+    fn test_storage_prove() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+
+        let key = b"key9".to_vec();
+        storage.insert(key.clone(), b"value".to_vec()).unwrap();
+        storage
+            .insert(b"key10".to_vec(), b"other".to_vec())
+            .unwrap();
+
+        let public_key = storage.public_key();
+        let proof = storage.prove(&key).unwrap();
+        assert_eq!(proof.root.merkle_root, storage.content_digest());
+        assert_eq!(proof.root, storage.signed_root().unwrap());
+        assert!(proof.verify(&public_key, &key, b"value"));
+        assert!(!proof.verify(&public_key, &key, b"other"));
+        let mut forged = proof.clone();
+        forged.root.generation += 1;
+        assert!(!forged.verify(&public_key, &key, b"value"));
+        assert!(storage.prove(b"missing").is_err());
+    }
+
+    #[test]
+    fn test_storage_state_signature() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+        drop(storage);
+
+        let dir = Directory::new(temp_dir.path().join(".zewos"));
+        let rewrite = |edit: &dyn Fn(&mut serde_json::Value)| {
+            let mut metadata: serde_json::Value =
+                serde_json::from_slice(&dir.metadata_file().read().unwrap()).unwrap();
+            edit(&mut metadata);
+            dir.metadata_file()
+                .write(&serde_json::to_vec(&metadata).unwrap())
+                .unwrap();
+        };
+        let signed = dir.metadata_file().read().unwrap();
+        rewrite(&|metadata| metadata["state_signature"] = serde_json::Value::Null);
+        assert!(matches!(
+            Storage::load(&store_path(origin), ZewosConfig::default()),
+            Err(StorageError::StateSignature)
+        ));
+
+        // A store from before it had a key is signed once the key is created.
+        std::fs::remove_file(temp_dir.path().join(".zewos").join("master.key")).unwrap();
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        drop(storage);
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![1]);
+        drop(storage);
+
+        dir.metadata_file().write(&signed).unwrap();
+        assert!(matches!(
+            Storage::load(&store_path(origin), ZewosConfig::default()),
+            Err(StorageError::StateSignature)
+        ));
+    }
+
+    #[test]
+    fn test_storage_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        storage.insert(b"cert".to_vec(), vec![1]).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+
+        let snapshot = storage.snapshot().unwrap();
+        let writer = {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                storage.insert(b"cert".to_vec(), vec![2]).unwrap();
+                storage.insert(b"key".to_vec(), vec![2]).unwrap();
+                storage.insert(b"new".to_vec(), vec![2]).unwrap();
+            })
+        };
+        writer.join().unwrap();
+
+        assert_eq!(snapshot.get(b"cert").unwrap(), vec![1]);
+        assert_eq!(snapshot.get(b"key").unwrap(), vec![1]);
+        assert!(!snapshot.contains_key(b"new"));
+        assert_eq!(snapshot.len(), 2);
+
+        let latest = storage.snapshot().unwrap();
+        assert_eq!(latest.get(b"key").unwrap(), vec![2]);
+        assert_eq!(latest.len(), 3);
+        assert!(latest.generation() > snapshot.generation());
+
+        let billing = storage.namespace("billing").unwrap();
+        billing.insert(b"card", vec![3]).unwrap();
+        let latest = storage.snapshot().unwrap();
+        assert_eq!(latest.len(), storage.len());
+        assert!(latest
+            .keys()
+            .all(|key| !key.starts_with(namespace::RESERVED_PREFIX)));
+        assert_eq!(latest.content_digest(), storage.content_digest());
+    }
+
+    #[test]
+    fn test_storage_namespaces() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        storage.insert(b"token".to_vec(), b"root".to_vec()).unwrap();
+
+        let billing = storage.namespace("billing").unwrap();
+        let search = storage.namespace("search").unwrap();
+        billing.insert(b"token", b"billing".to_vec()).unwrap();
+        search.insert(b"token", b"search".to_vec()).unwrap();
+        assert_eq!(billing.get(b"token").unwrap(), b"billing");
+        assert_eq!(search.get(b"token").unwrap(), b"search");
+        assert_eq!(storage.get(&b"token".to_vec()).unwrap(), b"root");
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage.get_all_keys().unwrap(), vec![b"token".to_vec()]);
+        assert_eq!(storage.namespaces().unwrap(), ["billing", "search"]);
+        assert!(matches!(
+            storage.insert(b"\0ns\0billing\0token".to_vec(), vec![1]),
+            Err(StorageError::ReservedKey)
+        ));
+        assert!(storage.namespace("").is_err());
+
+        storage
+            .configure_namespace("billing", NamespaceConfig::new().with_max_entries(2))
+            .unwrap();
+        billing.insert(b"second", vec![1]).unwrap();
+        billing.insert(b"second", vec![2]).unwrap();
+        assert!(matches!(
+            billing.insert(b"third", vec![3]),
+            Err(StorageError::QuotaExceeded { .. })
+        ));
+        let mut keys = billing.keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec![b"second".to_vec(), b"token".to_vec()]);
+
+        let archive = temp_dir.path().join("billing.zewos");
+        billing
+            .export_with_params(&archive, "passphrase", KdfParams::new(1_000))
+            .unwrap();
+        let target_dir = TempDir::new().unwrap();
+        let target = Storage::init(target_dir.path().to_str().unwrap()).unwrap();
+        target
+            .import(&archive, "passphrase", ConflictPolicy::Abort)
+            .unwrap();
+        assert_eq!(target.get(&b"token".to_vec()).unwrap(), b"billing");
+        assert_eq!(target.len(), 2);
+
+        assert_eq!(storage.delete_namespace("billing").unwrap(), 2);
+        assert!(billing.is_empty().unwrap());
+        assert_eq!(billing.config().unwrap(), NamespaceConfig::default());
+        assert_eq!(storage.namespaces().unwrap(), ["search"]);
+        assert_eq!(search.get(b"token").unwrap(), b"search");
+    }
+
+    #[test]
+    fn test_storage_scan() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        for key in ["db/prod/b", "db/dev/a", "db/prod/a", "web/a"] {
+            storage.insert(key.as_bytes().to_vec(), vec![1]).unwrap();
+        }
+        storage
+            .namespace("billing")
+            .unwrap()
+            .insert(b"db/prod/c", vec![1])
+            .unwrap();
+
+        assert_eq!(
+            storage.scan_prefix(b"db/prod/").unwrap(),
+            vec![b"db/prod/a".to_vec(), b"db/prod/b".to_vec()]
+        );
+        assert_eq!(
+            storage.range(b"db/prod/b".to_vec()..).unwrap(),
+            vec![b"db/prod/b".to_vec(), b"web/a".to_vec()]
+        );
+        assert_eq!(storage.get_all_keys().unwrap()[0], b"db/dev/a");
+
+        let page = storage
+            .scan(KeyScan::all().reverse().with_limit(3))
+            .unwrap();
+        assert_eq!(page.keys[0], b"web/a");
+        let rest = storage
+            .scan(KeyScan::all().reverse().with_cursor(page.next.unwrap()))
+            .unwrap();
+        assert_eq!(rest.keys, vec![b"db/dev/a".to_vec()]);
+        assert!(rest.next.is_none());
+
+        let billing = storage.namespace("billing").unwrap();
+        billing.insert(b"db/prod/a", vec![1]).unwrap();
+        let page = billing
+            .scan(KeyScan::prefix(b"db/prod/").with_limit(1))
+            .unwrap();
+        assert_eq!(page.keys, vec![b"db/prod/a".to_vec()]);
+        let page = billing
+            .scan(KeyScan::prefix(b"db/prod/").with_cursor(page.next.unwrap()))
+            .unwrap();
+        assert_eq!(page.keys, vec![b"db/prod/c".to_vec()]);
+    }
+
+    #[test]
+    fn test_storage_iterators() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        for i in (0..150u8).rev() {
+            storage.insert(vec![b'k', i], vec![i, 1]).unwrap();
+        }
+        storage
+            .namespace("hidden")
+            .unwrap()
+            .insert(b"key", vec![1])
+            .unwrap();
+
+        let keys: Vec<_> = storage.keys().unwrap().collect();
+        assert_eq!(keys.len(), 150);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let values: Vec<_> = storage.values().unwrap().map(Result::unwrap).collect();
+        assert_eq!(values[0], vec![0, 1]);
+        assert_eq!(values[149], vec![149, 1]);
+
+        let mut iter = storage.iter().unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), (vec![b'k', 0], vec![0, 1]));
+        storage.remove(&vec![b'k', 1]).unwrap();
+        assert_eq!(iter.count(), 148);
+
+        let entry = storage
+            .entries_with_metadata()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.key, vec![b'k', 0]);
+        assert_eq!(entry.metadata.get_size(), 2);
+        assert!(entry.metadata.get_created_at() <= entry.metadata.get_last_updated());
+    }
+
+    #[test]
+    fn test_storage_namespace_encryption() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        storage
+            .configure_namespace("vault", NamespaceConfig::new().with_encrypt(true))
+            .unwrap();
+        let vault = storage.namespace("vault").unwrap();
+        vault.insert(b"key", b"secret".to_vec()).unwrap();
+        assert_eq!(vault.get(b"key").unwrap(), b"secret");
+
+        let stored = storage
+            .inner
+            .index
+            .get(&b"\0ns\0vault\0key".to_vec())
+            .unwrap();
+        assert_ne!(stored, b"secret");
+        assert!(storage
+            .configure_namespace("vault", NamespaceConfig::new())
+            .is_err());
+
+        // Another store keys the same namespace differently.
+        let other_dir = TempDir::new().unwrap();
+        let other = Storage::init(other_dir.path().to_str().unwrap()).unwrap();
+        other
+            .configure_namespace("vault", NamespaceConfig::new().with_encrypt(true))
+            .unwrap();
+        other
+            .inner
+            .index
+            .insert(b"\0ns\0vault\0key".to_vec(), stored)
+            .unwrap();
+        assert!(other.namespace("vault").unwrap().get(b"key").is_err());
+    }
+
+    #[test]
+    fn test_storage_namespace_export_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = ZewosConfig::default()
+            .with_backup_config(zewos_storage::BackupConfig::new().with_history_limit(2));
+        let origin = temp_dir.path().join("source");
+        let storage = Storage::init_with_config(origin.to_str().unwrap(), config).unwrap();
+        storage
+            .configure_namespace("vault", NamespaceConfig::new().with_encrypt(true))
+            .unwrap();
+        let vault = storage.namespace("vault").unwrap();
+        vault.insert(b"key", b"first".to_vec()).unwrap();
+        vault.insert(b"key", b"second".to_vec()).unwrap();
+        vault.insert(b"gone", b"removed".to_vec()).unwrap();
+        vault.remove(b"gone").unwrap();
+
+        let archive = temp_dir.path().join("vault.zewos");
+        vault
+            .export_with_params(&archive, "passphrase", KdfParams::new(1_000))
+            .unwrap();
+        let origin = temp_dir.path().join("target");
+        let target = Storage::init_with_config(origin.to_str().unwrap(), config).unwrap();
+        target
+            .import(&archive, "passphrase", ConflictPolicy::Abort)
+            .unwrap();
+        assert_eq!(target.get_version(b"key", 1).unwrap(), b"first");
+        target.revert(b"gone".to_vec(), 1).unwrap();
+        assert_eq!(target.get(&b"gone".to_vec()).unwrap(), b"removed");
+    }
+
+    #[test]
+    fn test_storage_conditional_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        let key = b"key".to_vec();
+
+        let first = storage.insert_if_absent(key.clone(), vec![1]).unwrap();
+        assert!(matches!(
+            storage.insert_if_absent(key.clone(), vec![2]),
+            Err(StorageError::Conflict { current: Some(v) }) if v == first
+        ));
+
+        let second = storage
+            .compare_and_swap(key.clone(), Some(&[1]), vec![2])
+            .unwrap();
+        assert!(second > first);
+        assert!(matches!(
+            storage.compare_and_swap(key.clone(), Some(&[1]), vec![3]),
+            Err(StorageError::Conflict { current: Some(v) }) if v == second
+        ));
+        assert!(matches!(
+            storage.update(key.clone(), first, vec![3]),
+            Err(StorageError::Conflict { current: Some(v) }) if v == second
+        ));
+        assert_eq!(storage.get(&key).unwrap(), vec![2]);
+
+        // A removed and re-inserted entry never gets a version back.
+        storage.remove(&key).unwrap();
+        assert!(matches!(
+            storage.update(key.clone(), second, vec![3]),
+            Err(StorageError::Conflict { current: None })
+        ));
+        storage
+            .compare_and_swap(key.clone(), None, vec![2])
+            .unwrap();
+        assert!(storage.update(key.clone(), second, vec![3]).is_err());
+        let current = storage.version(&key).unwrap();
+        assert!(storage.update(key.clone(), current, vec![3]).unwrap() > current);
+        assert_eq!(storage.get(&key).unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn test_storage_ttl() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        let short = b"short".to_vec();
+        let long = b"long".to_vec();
+        storage
+            .insert_with_ttl(short.clone(), vec![1], Duration::from_millis(50))
+            .unwrap();
+        storage
+            .insert_with_ttl(long.clone(), vec![2], Duration::from_secs(3600))
+            .unwrap();
+        assert_eq!(storage.get(&short).unwrap(), vec![1]);
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(matches!(storage.get(&short), Err(StorageError::Expired)));
+        assert!(!storage.contains_key(&short).unwrap());
+        assert!(storage.snapshot().unwrap().get(&short).is_err());
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage.get_all_keys().unwrap(), vec![long.clone()]);
+        assert_eq!(
+            storage.keys().unwrap().collect::<Vec<_>>(),
+            vec![long.clone()]
+        );
+        assert_eq!(
+            storage.scan(KeyScan::all()).unwrap().keys,
+            vec![long.clone()]
+        );
+        assert_eq!(storage.index().len(), 2);
+
+        storage.save().unwrap();
+        assert_eq!(storage.index().len(), 1);
+        assert_eq!(storage.purge_expired().unwrap(), 0);
+        drop(storage);
+
+        let storage = Storage::load(&store_path(origin), ZewosConfig::default()).unwrap();
+        assert_eq!(storage.get(&long).unwrap(), vec![2]);
+        storage
+            .insert_with_ttl(short.clone(), vec![1], Duration::from_millis(20))
+            .unwrap();
+        let _sweeper = storage.sweep(Duration::from_millis(10));
+        for _ in 0..100 {
+            if storage.index().len() == 1 {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("expired entry was not swept");
+    }
+
+    #[test]
+    fn test_storage_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let config = ZewosConfig::default()
+            .with_backup_config(zewos_storage::BackupConfig::new().with_history_limit(2));
+        let storage = Storage::init_with_config(origin, config).unwrap();
+        let key = b"key".to_vec();
+        for value in 1..=4 {
+            storage.insert(key.clone(), vec![value]).unwrap();
+        }
+
+        let history = storage.history(&key).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].value, vec![3]);
+        assert!(history[0].metadata.get_last_updated() >= history[1].metadata.get_last_updated());
+        assert_eq!(storage.get_version(&key, 0).unwrap(), vec![4]);
+        assert_eq!(storage.get_version(&key, 2).unwrap(), vec![2]);
+        assert!(matches!(
+            storage.get_version(&key, 3),
+            Err(StorageError::VersionNotFound)
+        ));
+
+        storage.revert(key.clone(), 2).unwrap();
+        assert_eq!(storage.get(&key).unwrap(), vec![2]);
+        assert_eq!(storage.get_version(&key, 1).unwrap(), vec![4]);
+
+        let snapshot = storage.snapshot().unwrap();
+        storage.insert(key.clone(), vec![5]).unwrap();
+        assert_eq!(snapshot.get_version(&key, 1).unwrap(), vec![4]);
+
+        let archive = temp_dir.path().join("export.zewos");
+        storage
+            .export_with_params(&archive, "passphrase", KdfParams::new(1_000))
+            .unwrap();
+        storage.remove(&key).unwrap();
+        assert_eq!(storage.history(&key).unwrap()[0].value, vec![5]);
+        assert!(matches!(
+            storage.get_version(&key, 0),
+            Err(StorageError::KeyNotFound)
+        ));
+        storage.revert(key.clone(), 1).unwrap();
+        assert_eq!(storage.get(&key).unwrap(), vec![5]);
+        storage.remove(&key).unwrap();
+        drop(storage);
+
+        let storage = Storage::load(&store_path(origin), config).unwrap();
+        storage
+            .import(&archive, "passphrase", ConflictPolicy::Overwrite)
+            .unwrap();
+        assert_eq!(storage.get(&key).unwrap(), vec![5]);
+        assert_eq!(storage.get_version(&key, 2).unwrap(), vec![4]);
+    }
+
+    #[test]
+    fn test_storage_audit_sink() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Collect(Arc<Mutex<Vec<Log>>>);
+        impl AuditSink for Collect {
+            fn record(&mut self, log: Log) -> std::io::Result<()> {
+                self.0.lock().unwrap().push(log);
+                Ok(())
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let sink = Collect::default();
+        let storage = Storage::init(origin).unwrap().with_audit_sink(sink.clone());
+        storage.insert(b"secret".to_vec(), vec![1]).unwrap();
+        assert!(storage.get(&b"missing".to_vec()).is_err());
+
+        let records = sink.0.lock().unwrap();
+        let operations: Vec<_> = records.iter().map(|log| log.operation()).collect();
+        assert_eq!(operations, ["insert", "save", "get"]);
+        assert_eq!(records[2].error_kind(), Some(ErrorKind::KeyNotFound));
+        assert_eq!(records[2].error_id(), Storage::last_error_id().as_deref());
+        assert!(records
+            .iter()
+            .all(|log| log.key_id().is_none_or(|key_id| !key_id.contains("secret"))));
+        assert_eq!(storage.query_logs(LogQuery::new()).unwrap().count(), 1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_storage_tracing_spans() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// The `operation` and `key_id` fields of a span or event.
+        #[derive(Debug, Clone, Default, PartialEq)]
+        struct Fields {
+            operation: String,
+            key_id: Option<String>,
+        }
+
+        impl Visit for Fields {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                match field.name() {
+                    "operation" => self.operation = value.to_string(),
+                    "key_id" => self.key_id = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+
+            fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+        }
+
+        /// Operation of each event, with the fields of the span it was emitted in.
+        type Events = Arc<Mutex<Vec<(Option<Fields>, String)>>>;
+
+        /// Collects each event along with the span it was emitted in.
+        #[derive(Default)]
+        struct Collect {
+            next: AtomicU64,
+            spans: Mutex<Vec<Fields>>,
+            entered: Mutex<Vec<usize>>,
+            events: Events,
+        }
+
+        impl Subscriber for Collect {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut fields = Fields::default();
+                span.record(&mut fields);
+                self.spans.lock().unwrap().push(fields);
+                Id::from_u64(self.next.fetch_add(1, Ordering::SeqCst) + 1)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                let span = self
+                    .entered
+                    .lock()
+                    .unwrap()
+                    .last()
+                    .map(|index| self.spans.lock().unwrap()[*index].clone());
+                self.events.lock().unwrap().push((span, fields.operation));
+            }
+
+            fn enter(&self, span: &Id) {
+                let index = span.into_u64() as usize - 1;
+                self.entered.lock().unwrap().push(index);
+            }
+
+            fn exit(&self, _: &Id) {
+                self.entered.lock().unwrap().pop();
+            }
+        }
+
+        let collect = Collect::default();
+        let events = collect.events.clone();
+        let temp_dir = TempDir::new().unwrap();
+        tracing::subscriber::with_default(collect, || {
+            let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+            storage.insert(b"secret".to_vec(), vec![1]).unwrap();
+            assert!(storage.get(&b"missing".to_vec()).is_err());
+        });
+
+        let events = events.lock().unwrap();
+        let operations: Vec<_> = events
+            .iter()
+            .map(|(span, event)| (span.as_ref().unwrap().operation.as_str(), event.as_str()))
+            .collect();
+        assert_eq!(
+            operations,
+            [
+                ("init", "init"),
+                ("insert", "insert"),
+                ("insert", "save"),
+                ("get", "get")
+            ]
+        );
+        let key_id = events[1].0.as_ref().unwrap().key_id.as_deref().unwrap();
+        assert!(key_id.starts_with("hmac-") && !key_id.contains("secret"));
+    }
+
+    #[test]
+    fn test_storage_error_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        assert!(storage.get(&b"missing".to_vec()).is_err());
+        let error_id = Storage::last_error_id().unwrap();
+
+        let logged: Vec<Log> = storage
+            .query_logs(LogQuery::new().with_error_id(&error_id))
+            .unwrap()
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].operation(), "get");
+        assert_eq!(logged[0].error_kind(), Some(ErrorKind::KeyNotFound));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_storage_syslog_fallback() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let socket_path = temp_dir.path().join("missing.sock");
+        let storage = Storage::init(origin).unwrap().with_syslog(&socket_path);
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+        storage.insert(b"key".to_vec(), vec![2]).unwrap();
+        let operations: Vec<_> = storage
+            .query_logs(LogQuery::new())
+            .unwrap()
+            .map(|log| log.unwrap().operation().to_string())
+            .collect();
+        assert_eq!(operations, ["init", "insert", "save", "insert", "save"]);
+        assert!(storage.verify_logs().unwrap().is_valid());
+        drop(storage);
+
+        let storage = Storage::open_read_only(&store_path(origin), ZewosConfig::default())
+            .unwrap()
+            .with_syslog(&socket_path);
+        // Reads are only queued, so the record that cannot be written fails the next
+        // operation that waits for its record instead.
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![2]);
+        assert!(matches!(
+            storage.insert(b"key".to_vec(), vec![3]),
+            Err(StorageError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_storage_log_level() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let config = ZewosConfig::default().with_log_level(LogLevel::Writes);
+        let storage = Storage::init_with_config(origin, config).unwrap();
+        storage.insert(b"key".to_vec(), vec![1]).unwrap();
+        storage.get(&b"key".to_vec()).unwrap();
+        assert!(storage.get(&b"missing".to_vec()).is_err());
+        assert!(!storage.is_empty());
+
+        let operations: Vec<String> = storage
+            .query_logs(LogQuery::new())
+            .unwrap()
+            .map(|log| log.unwrap().operation().to_string())
+            .collect();
+        assert_eq!(operations, ["init", "insert", "save", "get"]);
+        let failure = storage
+            .query_logs(LogQuery::new().with_outcome(Outcome::Failure))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(failure.error_kind(), Some(ErrorKind::KeyNotFound));
+    }
+
+    #[test]
+    fn test_storage_shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Storage>();
+
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        storage.insert(b"shared".to_vec(), vec![0]).unwrap();
+
+        let handles: Vec<_> = (0..4u8)
+            .map(|i| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    storage.insert(vec![i], vec![i]).unwrap();
+                    assert_eq!(storage.get(&b"shared".to_vec()).unwrap(), vec![0]);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(storage.len(), 5);
+        drop(storage);
+        let storage = Storage::load(
+            &store_path(temp_dir.path().to_str().unwrap()),
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(storage.len(), 5);
+        assert!(storage.verify_logs().unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_storage_export_import() {
+        let source_dir = TempDir::new().unwrap();
+        let source = Storage::init(source_dir.path().to_str().unwrap()).unwrap();
+        source
+            .insert(b"shared".to_vec(), b"from_source".to_vec())
+            .unwrap();
+        source.insert(b"only_source".to_vec(), vec![1]).unwrap();
+
+        let archive = source_dir.path().join("export.zewos");
+        source
+            .export_with_params(&archive, "passphrase", KdfParams::new(1_000))
+            .unwrap();
+
+        let target_dir = TempDir::new().unwrap();
+        let target = Storage::init(target_dir.path().to_str().unwrap()).unwrap();
+        target
+            .insert(b"shared".to_vec(), b"from_target".to_vec())
+            .unwrap();
+
+        assert!(target
+            .import(&archive, "wrong", ConflictPolicy::Overwrite)
+            .is_err());
+
+        let report = target
+            .import(&archive, "passphrase", ConflictPolicy::Skip)
+            .unwrap();
+        assert_eq!(report.inserted, 1);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(target.get(&b"shared".to_vec()).unwrap(), b"from_target");
+        assert_eq!(target.get(&b"only_source".to_vec()).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_storage_import_rejects_reserved_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().join("store").to_str().unwrap()).unwrap();
+        storage
+            .namespace("vault")
+            .unwrap()
+            .insert(b"key", vec![1])
+            .unwrap();
+        let archive = temp_dir.path().join("export.zewos");
+        storage
+            .export_with_params(&archive, "passphrase", KdfParams::new(1_000))
+            .unwrap();
+        let report = storage
+            .import(&archive, "passphrase", ConflictPolicy::Abort)
+            .unwrap();
+        assert_eq!(report.inserted, 0);
+
+        let forged = Storage::init(temp_dir.path().join("forged").to_str().unwrap()).unwrap();
+        forged
+            .inner
+            .index
+            .insert(namespace::config_key("vault"), vec![0])
+            .unwrap();
+        let payload = forged.inner.index.export_backup(|_| true).unwrap();
+        Archive::seal_with_params(&payload, "passphrase", KdfParams::new(1_000))
+            .unwrap()
+            .write(&archive)
+            .unwrap();
+        assert!(matches!(
+            storage.import(&archive, "passphrase", ConflictPolicy::Overwrite),
+            Err(StorageError::ReservedKey)
+        ));
+        assert_eq!(
+            storage.namespace("vault").unwrap().get(b"key").unwrap(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_storage_failed_save_returns_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().to_str().unwrap();
+        let storage = Storage::init(origin).unwrap();
+        let objects = Directory::file_paths(Path::new(&store_path(origin)))[0].clone();
+        std::fs::remove_file(&objects).unwrap();
+        std::fs::create_dir(&objects).unwrap();
+        assert!(storage.insert(b"key".to_vec(), vec![1]).is_err());
+
+        std::fs::remove_dir(&objects).unwrap();
+        storage.insert(b"key".to_vec(), vec![2]).unwrap();
+        assert_eq!(storage.get(&b"key".to_vec()).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_storage_failed_export_is_audited() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::init(temp_dir.path().to_str().unwrap()).unwrap();
+        let archive = temp_dir.path().join("missing").join("export.zewos");
+        assert!(storage
+            .export_with_params(&archive, "passphrase", KdfParams::new(1_000))
+            .is_err());
+        let error_id = Storage::last_error_id().unwrap();
+        let logged: Vec<Log> = storage
+            .query_logs(LogQuery::new().with_error_id(&error_id))
+            .unwrap()
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(logged[0].operation(), "export");
+    }
 }